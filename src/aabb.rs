@@ -0,0 +1,265 @@
+use crate::game::{CollisionDetectionFlag, GameMap, GamePosition, GAME_MAP_X, GAME_MAP_Y, TILE_SIZE};
+
+/// Axis aligned box given by its center and half its size.
+///
+/// Entities keep the bottom-left corner in `GamePosition` and the full size in
+/// `CollisionDetectionFlag`, `Aabb::of` is the one place that turns those into a box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub center: [f32; 2],
+    pub half_extents: [f32; 2],
+}
+
+impl Aabb {
+    pub fn new(center: [f32; 2], half_extents: [f32; 2]) -> Self {
+        Self { center, half_extents }
+    }
+
+    pub fn of(pos: &GamePosition, coll: &CollisionDetectionFlag) -> Self {
+        let half_extents = [coll.0[0] / 2., coll.0[1] / 2.];
+        Self::new([pos.0[0] + half_extents[0], pos.0[1] + half_extents[1]], half_extents)
+    }
+
+    pub fn tile((x, y): (usize, usize)) -> Self {
+        let half = TILE_SIZE as f32 / 2.;
+        Self::new([x as f32 * TILE_SIZE as f32 + half, y as f32 * TILE_SIZE as f32 + half], [half, half])
+    }
+
+    pub fn min(&self) -> [f32; 2] {
+        [self.center[0] - self.half_extents[0], self.center[1] - self.half_extents[1]]
+    }
+
+    pub fn max(&self) -> [f32; 2] {
+        [self.center[0] + self.half_extents[0], self.center[1] + self.half_extents[1]]
+    }
+
+    /// The bottom-left corner, what goes back into `GamePosition`
+    pub fn corner(&self) -> GamePosition {
+        GamePosition(self.min())
+    }
+
+    /// Boxes that only share an edge don't overlap
+    pub fn overlaps(&self, other: &Aabb) -> bool {
+        (self.center[0] - other.center[0]).abs() < self.half_extents[0] + other.half_extents[0]
+            && (self.center[1] - other.center[1]).abs() < self.half_extents[1] + other.half_extents[1]
+    }
+
+    pub fn translated(&self, idx: usize, amount: f32) -> Self {
+        let mut out = *self;
+        out.center[idx] += amount;
+        out
+    }
+
+    /// Map tiles the box covers, leaving out any outside the map
+    pub fn tiles(&self) -> Vec<(usize, usize)> {
+        let tile = |v: f32| (v / TILE_SIZE as f32).floor() as i32;
+        let (min, max) = (self.min(), self.max());
+        // A box ending exactly on a tile edge doesn't reach into the next tile
+        let x1 = (tile(max[0]) - if max[0] % TILE_SIZE as f32 == 0. { 1 } else { 0 }).min(GAME_MAP_X as i32 - 1);
+        let y1 = (tile(max[1]) - if max[1] % TILE_SIZE as f32 == 0. { 1 } else { 0 }).min(GAME_MAP_Y as i32 - 1);
+        let x0 = tile(min[0]).max(0);
+        let y0 = tile(min[1]).max(0);
+
+        let mut out = Vec::new();
+        for x in x0..=x1 {
+            for y in y0..=y1 {
+                out.push((x as usize, y as usize));
+            }
+        }
+        out
+    }
+
+    /// Whether any tile the box covers blocks, nothing blocks outside the map
+    pub fn blocked(&self, game_map: &GameMap) -> bool {
+        self.tiles().into_iter().any(|tile| game_map.blocks(tile))
+    }
+
+    /// Unit normal of the wall face the box runs into moving by `step`, zero when it runs into
+    /// none. Running into a corner gets the normal of both faces.
+    pub fn wall_normal(&self, step: [f32; 2], game_map: &GameMap) -> [f32; 2] {
+        let moved = self.translated(0, step[0]).translated(1, step[1]);
+        if !moved.blocked(game_map) {
+            return [0., 0.];
+        }
+
+        let hit_x = step[0] != 0. && self.translated(0, step[0]).blocked(game_map);
+        let hit_y = step[1] != 0. && self.translated(1, step[1]).blocked(game_map);
+        let away = |v: f32| if v == 0. { 0. } else { -v.signum() };
+
+        let nx = if hit_x || !hit_y { away(step[0]) } else { 0. };
+        let ny = if hit_y || !hit_x { away(step[1]) } else { 0. };
+        let len = (nx * nx + ny * ny).sqrt();
+        if len == 0. {
+            [0., 0.]
+        } else {
+            [nx / len, ny / len]
+        }
+    }
+
+    /// How far the box can go along axis `idx` toward `amount` before touching a blocking
+    /// tile. Moving one axis at a time is what lets entities slide along walls. A box already
+    /// stuck in a wall may move out of it, but not further in.
+    pub fn sweep_axis(&self, idx: usize, amount: f32, game_map: &GameMap) -> f32 {
+        let mut allowed = amount;
+        for tile in self.translated(idx, amount).tiles() {
+            if !game_map.blocks(tile) {
+                continue;
+            }
+            let wall = Aabb::tile(tile);
+            if self.overlaps(&wall) {
+                let before = (self.center[idx] - wall.center[idx]).abs();
+                let after = (self.center[idx] + amount - wall.center[idx]).abs();
+                if after < before {
+                    allowed = 0.;
+                }
+            } else if amount > 0. {
+                allowed = allowed.min((wall.min()[idx] - self.max()[idx]).max(0.));
+            } else if amount < 0. {
+                allowed = allowed.max((wall.max()[idx] - self.min()[idx]).min(0.));
+            }
+        }
+        allowed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::GameMapTile;
+
+    const TILE: f32 = TILE_SIZE as f32;
+
+    /// An empty map with walls on the given tiles
+    fn map_with_walls(walls: &[(usize, usize)]) -> GameMap {
+        let mut tiles = [[GameMapTile::Floor; GAME_MAP_Y]; GAME_MAP_X];
+        for &(x, y) in walls {
+            tiles[x][y] = GameMapTile::Wall;
+        }
+        GameMap::new(tiles, 0)
+    }
+
+    #[test]
+    fn of_takes_the_bottom_left_corner() {
+        let aabb = Aabb::of(&GamePosition([100., 200.]), &CollisionDetectionFlag([40., 20.]));
+        assert_eq!(aabb.center, [120., 210.]);
+        assert_eq!(aabb.half_extents, [20., 10.]);
+        assert_eq!(aabb.min(), [100., 200.]);
+        assert_eq!(aabb.corner().0, [100., 200.]);
+    }
+
+    #[test]
+    fn shared_edge_is_not_an_overlap() {
+        let a = Aabb::new([0., 0.], [10., 10.]);
+        assert!(!a.overlaps(&Aabb::new([20., 0.], [10., 10.])));
+        assert!(!a.overlaps(&Aabb::new([0., -20.], [10., 10.])));
+        assert!(!a.overlaps(&Aabb::new([20., 20.], [10., 10.])));
+    }
+
+    #[test]
+    fn fractional_overlap() {
+        let a = Aabb::new([0., 0.], [10., 10.]);
+        assert!(a.overlaps(&Aabb::new([19.9, 0.], [10., 10.])));
+        assert!(a.overlaps(&Aabb::new([-19.9, 19.9], [10., 10.])));
+    }
+
+    #[test]
+    fn clock_box_is_not_square() {
+        // Wall clocks are 60 wide and 100 tall
+        let clock = Aabb::of(&GamePosition([0., 0.]), &CollisionDetectionFlag([60., 100.]));
+        assert_eq!(clock.max(), [60., 100.]);
+        assert!(clock.overlaps(&Aabb::new([30., 99.], [1., 1.])));
+        assert!(!clock.overlaps(&Aabb::new([61., 50.], [1., 1.])));
+        assert_eq!(clock.tiles(), vec![(0, 0), (0, 1)]);
+    }
+
+    #[test]
+    fn tiles_stop_at_a_tile_edge() {
+        assert_eq!(Aabb::tile((2, 3)).tiles(), vec![(2, 3)]);
+
+        let across =
+            Aabb::of(&GamePosition([TILE - 1., TILE]), &CollisionDetectionFlag([2., TILE]));
+        assert_eq!(across.tiles(), vec![(0, 1), (1, 1)]);
+    }
+
+    #[test]
+    fn tiles_leave_out_the_outside_of_the_map() {
+        let aabb = Aabb::new([0., 0.], [10., 10.]);
+        assert_eq!(aabb.tiles(), vec![(0, 0)]);
+    }
+
+    #[test]
+    fn slides_flush_against_a_wall() {
+        let map = map_with_walls(&[(3, 2), (1, 2)]);
+        // 50 wide box in tile (2, 2), 5 away from the walls either side
+        let aabb = Aabb::of(
+            &GamePosition([2. * TILE + 5., 2. * TILE + 10.]),
+            &CollisionDetectionFlag([50., 40.]),
+        );
+
+        assert_eq!(aabb.sweep_axis(0, 20., &map), 5.);
+        assert_eq!(aabb.sweep_axis(0, -20., &map), -5.);
+        assert_eq!(aabb.sweep_axis(0, 3., &map), 3.);
+
+        // Flush against the wall it can't go further, but can still move along it
+        let flush = aabb.translated(0, 5.);
+        assert_eq!(flush.sweep_axis(0, 10., &map), 0.);
+        assert_eq!(flush.sweep_axis(1, 10., &map), 10.);
+        assert_eq!(flush.sweep_axis(1, -10., &map), -10.);
+        assert_eq!(flush.sweep_axis(0, -5., &map), -5.);
+    }
+
+    #[test]
+    fn slides_flush_against_a_floor_and_ceiling() {
+        let map = map_with_walls(&[(2, 3), (2, 1)]);
+        let aabb = Aabb::of(
+            &GamePosition([2. * TILE + 10., 2. * TILE + 5.]),
+            &CollisionDetectionFlag([40., 50.]),
+        );
+
+        assert_eq!(aabb.sweep_axis(1, 20., &map), 5.);
+        assert_eq!(aabb.sweep_axis(1, -20., &map), -5.);
+    }
+
+    #[test]
+    fn blocked_by_a_wall_under_any_edge() {
+        let map = map_with_walls(&[(3, 2)]);
+        // Corner in the floor tile (2, 2), right edge reaching into the wall
+        let aabb = Aabb::of(
+            &GamePosition([3. * TILE - 8., 2. * TILE + 10.]),
+            &CollisionDetectionFlag([10., 10.]),
+        );
+        assert!(aabb.blocked(&map));
+        assert!(!aabb.translated(0, -2.).blocked(&map));
+
+        let top = Aabb::of(
+            &GamePosition([3. * TILE + 10., 2. * TILE - 8.]),
+            &CollisionDetectionFlag([10., 10.]),
+        );
+        assert!(top.blocked(&map));
+    }
+
+    #[test]
+    fn wall_normal_faces_back_the_way_it_came() {
+        let map = map_with_walls(&[(3, 2), (2, 3)]);
+        let aabb = Aabb::new([2. * TILE + TILE / 2., 2. * TILE + TILE / 2.], [5., 5.]);
+
+        assert_eq!(aabb.wall_normal([TILE / 2., 0.], &map), [-1., 0.]);
+        assert_eq!(aabb.wall_normal([0., TILE / 2.], &map), [0., -1.]);
+        assert_eq!(aabb.wall_normal([-TILE / 2., 0.], &map), [0., 0.]);
+
+        let corner = aabb.wall_normal([TILE / 2., TILE / 2.], &map);
+        assert!((corner[0] + 0.707).abs() < 0.01 && (corner[1] + 0.707).abs() < 0.01);
+    }
+
+    #[test]
+    fn can_leave_a_wall_it_is_stuck_in() {
+        let map = map_with_walls(&[(2, 2)]);
+        // Half in the wall tile, half in the floor tile to its left
+        let aabb = Aabb::new([2. * TILE, 2. * TILE + TILE / 2.], [10., 10.]);
+
+        assert_eq!(aabb.sweep_axis(0, -5., &map), -5.);
+        assert_eq!(aabb.sweep_axis(0, 5., &map), 0.);
+        assert_eq!(aabb.sweep_axis(1, 5., &map), 5.);
+        assert_eq!(aabb.sweep_axis(1, -5., &map), -5.);
+    }
+}
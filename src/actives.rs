@@ -0,0 +1,129 @@
+use crate::game::{Item, ItemType};
+use amethyst::ecs::prelude::{Component, VecStorage};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ActiveKind {
+    /// Clears the enemy bullets around the player
+    Bomb,
+    /// Enemies stop moving, shooting and running down their clocks for a while
+    TimeStop,
+    /// Every enemy that dies for a while gives back time
+    Harvest,
+}
+
+/// How far from the player's center a bomb clears bullets
+pub const BOMB_RADIUS: f32 = 240.;
+/// Seconds given back for each kill while a harvest lasts
+pub const HARVEST_REFUND: u64 = 3;
+
+impl ActiveKind {
+    /// Rooms that have to be cleared between uses
+    pub fn rooms_to_charge(self) -> u32 {
+        match self {
+            ActiveKind::Bomb => 1,
+            ActiveKind::TimeStop => 2,
+            ActiveKind::Harvest => 2,
+        }
+    }
+
+    /// How long it keeps working once used, zero for the ones that happen at once
+    pub fn duration(self) -> Duration {
+        match self {
+            ActiveKind::Bomb => Duration::from_secs(0),
+            ActiveKind::TimeStop => Duration::from_secs(4),
+            ActiveKind::Harvest => Duration::from_secs(10),
+        }
+    }
+}
+
+/// The one active item the player can carry. The charge belongs to the slot rather than the
+/// item, so swapping items doesn't refill it.
+#[derive(Component, Debug, Default)]
+#[storage(VecStorage)]
+pub struct ActiveItem {
+    pub held: Option<ActiveKind>,
+    /// Rooms cleared with an item held since it was last used, never more than it needs
+    pub charge: u32,
+    /// The one last used and how much longer it lasts
+    pub effect: Option<(ActiveKind, Duration)>,
+}
+
+impl ActiveItem {
+    pub fn ready(&self) -> bool {
+        self.held
+            .map_or(false, |kind| self.charge >= kind.rooms_to_charge())
+    }
+
+    pub fn is_active(&self, kind: ActiveKind) -> bool {
+        self.effect.map_or(false, |(active, _)| active == kind)
+    }
+
+    /// Takes the held item's charge and starts its effect, `None` when it isn't ready
+    pub fn use_item(&mut self) -> Option<ActiveKind> {
+        if !self.ready() {
+            return None;
+        }
+
+        let kind = self.held.unwrap();
+        self.charge = 0;
+        if kind.duration() > Duration::from_secs(0) {
+            self.effect = Some((kind, kind.duration()));
+        }
+        Some(kind)
+    }
+
+    /// Counts a cleared room toward the held item, nothing charges while the slot is empty
+    pub fn recharge(&mut self) {
+        if let Some(kind) = self.held {
+            self.charge = (self.charge + 1).min(kind.rooms_to_charge());
+        }
+    }
+
+    pub fn tick(&mut self, delta: Duration) {
+        self.effect = match self.effect {
+            Some((kind, left)) if left > delta => Some((kind, left - delta)),
+            _ => None,
+        };
+    }
+
+    /// Puts an active item in the slot, returning the one it replaced so it can be dropped
+    pub fn apply_item(&mut self, item: &Item) -> Option<ActiveKind> {
+        match item.kind {
+            ItemType::Active(kind) => self.held.replace(kind),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pick_up(active: &mut ActiveItem, kind: ActiveKind) -> Option<ActiveKind> {
+        active.apply_item(&Item::new((0, 0), ItemType::Active(kind), 0))
+    }
+
+    #[test]
+    fn nothing_charges_with_an_empty_slot() {
+        let mut active = ActiveItem::default();
+        active.recharge();
+        active.recharge();
+        pick_up(&mut active, ActiveKind::TimeStop);
+        assert_eq!(active.charge, 0);
+        assert!(!active.ready());
+    }
+
+    #[test]
+    fn charge_stops_at_what_the_item_needs() {
+        let mut active = ActiveItem::default();
+        pick_up(&mut active, ActiveKind::Bomb);
+        active.recharge();
+        active.recharge();
+        assert_eq!(active.charge, 1);
+        assert_eq!(active.use_item(), Some(ActiveKind::Bomb));
+        assert_eq!(active.charge, 0);
+        assert_eq!(active.use_item(), None);
+    }
+}
@@ -0,0 +1,49 @@
+use amethyst::ecs::prelude::{Component, DenseVecStorage, Entity};
+
+pub const LAYER_PLAYER: u32 = 1 << 0;
+pub const LAYER_ENEMY: u32 = 1 << 1;
+pub const LAYER_PLAYER_BULLET: u32 = 1 << 2;
+pub const LAYER_ENEMY_BULLET: u32 = 1 << 3;
+
+/// What an entity is (`layer`) and what it wants to hear about touching (`mask`).
+/// `DetectCollisions` only sends an event when the mask of one side has the layer of the other.
+#[derive(Component, Clone, Copy, Debug)]
+#[storage(DenseVecStorage)]
+pub struct CollisionLayer {
+    pub layer: u32,
+    pub mask: u32,
+}
+
+impl CollisionLayer {
+    pub fn new(layer: u32, mask: u32) -> Self {
+        Self { layer, mask }
+    }
+
+    pub fn player() -> Self {
+        Self::new(LAYER_PLAYER, LAYER_ENEMY)
+    }
+
+    pub fn enemy() -> Self {
+        Self::new(LAYER_ENEMY, LAYER_ENEMY)
+    }
+
+    pub fn player_bullet() -> Self {
+        Self::new(LAYER_PLAYER_BULLET, LAYER_ENEMY)
+    }
+
+    pub fn enemy_bullet() -> Self {
+        Self::new(LAYER_ENEMY_BULLET, LAYER_PLAYER)
+    }
+
+    pub fn wants(&self, other: &CollisionLayer) -> bool {
+        self.mask & other.layer != 0
+    }
+}
+
+/// `a` touched `b` this tick, and `a`'s mask has `b`'s layer.
+/// When both masks match each other there is one event each way.
+#[derive(Clone, Copy, Debug)]
+pub struct CollisionEvent {
+    pub a: Entity,
+    pub b: Entity,
+}
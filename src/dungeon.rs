@@ -0,0 +1,284 @@
+use crate::game::{GameMapTile, Item, GAME_MAP_X, GAME_MAP_Y, GENERATED_LEVEL};
+use crate::game_scale::{get_rooms_per_floor, use_generated_level};
+use crate::levels::{reachable_tiles, LevelSet};
+use crate::maps::game_map_tiles;
+use crate::procgen::generate_level;
+use itertools::iproduct;
+use rand::{seq::SliceRandom, Rng};
+use std::collections::HashMap;
+
+const MAX_LEVEL_ATTEMPTS: usize = 10;
+/// Floors with fewer rooms than this have no shop, there is no room to spare besides the
+/// entrance and the exit
+const MIN_ROOMS_FOR_SHOP: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DoorSide {
+    North,
+    South,
+    East,
+    West,
+}
+
+pub const DOOR_SIDES: [DoorSide; 4] = [
+    DoorSide::North,
+    DoorSide::South,
+    DoorSide::East,
+    DoorSide::West,
+];
+
+impl DoorSide {
+    pub fn opposite(self) -> Self {
+        use self::DoorSide::*;
+        match self {
+            North => South,
+            South => North,
+            East => West,
+            West => East,
+        }
+    }
+
+    /// Step in tiles, `x` grows to the east and `y` to the north
+    pub fn offset(self) -> (i32, i32) {
+        use self::DoorSide::*;
+        match self {
+            North => (0, 1),
+            South => (0, -1),
+            East => (1, 0),
+            West => (-1, 0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RoomKind {
+    Combat,
+    /// No enemies, items are laid out around `counter` and bought for time
+    Shop { counter: (usize, usize) },
+}
+
+pub struct DungeonRoom {
+    pub map: game_map_tiles,
+    pub neighbours: [Option<usize>; 4],
+    pub doors: [Option<(usize, usize)>; 4],
+    pub cleared: bool,
+    pub is_exit: bool,
+    /// Index into the `LevelSet`, or `GENERATED_LEVEL`
+    pub level: usize,
+    pub kind: RoomKind,
+    /// What a shop still has for sale, filled the first time it is entered
+    pub stock: Option<Vec<Item>>,
+    /// Active items swapped out here, put back out whenever the room is entered
+    pub dropped: Vec<Item>,
+    /// Whether clearing it has charged the active item yet, rooms that start cleared never do
+    pub charged: bool,
+}
+
+impl DungeonRoom {
+    /// The floor tile just inside the door on `side`, where the player walks in.
+    pub fn entry_tile(&self, side: DoorSide) -> Option<(usize, usize)> {
+        let (x, y) = self.doors[side as usize]?;
+        let (dx, dy) = side.offset();
+        Some(((x as i32 - dx) as usize, (y as i32 - dy) as usize))
+    }
+}
+
+/// The rooms making up the current floor. Room 0 is where the player starts, the teleport
+/// to the next floor only exists in the room with `is_exit` set.
+#[derive(Default)]
+pub struct DungeonFloor {
+    pub rooms: Vec<DungeonRoom>,
+    pub current: usize,
+}
+
+impl DungeonFloor {
+    pub fn current_room(&self) -> &DungeonRoom {
+        &self.rooms[self.current]
+    }
+
+    pub fn current_room_mut(&mut self) -> &mut DungeonRoom {
+        &mut self.rooms[self.current]
+    }
+}
+
+fn pick_level<R: Rng>(
+    floor: u32,
+    levels: &LevelSet,
+    last_level: usize,
+    rng: &mut R,
+) -> (usize, game_map_tiles) {
+    if use_generated_level(floor) {
+        (GENERATED_LEVEL, generate_level(rng.gen()))
+    } else {
+        let mut choice = rng.gen_range(0, levels.len());
+        while levels.len() > 1 && choice == last_level {
+            choice = rng.gen_range(0, levels.len());
+        }
+        (choice, levels.0[choice].map)
+    }
+}
+
+pub fn generate_floor<R: Rng>(
+    floor: u32,
+    levels: &LevelSet,
+    last_level: usize,
+    rng: &mut R,
+) -> DungeonFloor {
+    let num_rooms = get_rooms_per_floor(floor);
+
+    // Lay the rooms out on a grid, each new room hanging off a random existing one
+    let mut grid: HashMap<(i32, i32), usize> = HashMap::new();
+    let mut cells = vec![(0, 0)];
+    let mut depth = vec![0];
+    let mut links: Vec<[Option<usize>; 4]> = vec![[None; 4]];
+    grid.insert((0, 0), 0);
+
+    while cells.len() < num_rooms {
+        let parent = rng.gen_range(0, cells.len());
+        let side = *DOOR_SIDES.choose(rng).unwrap();
+        let (dx, dy) = side.offset();
+        let cell = (cells[parent].0 + dx, cells[parent].1 + dy);
+        if grid.contains_key(&cell) {
+            continue;
+        }
+
+        let idx = cells.len();
+        grid.insert(cell, idx);
+        cells.push(cell);
+        depth.push(depth[parent] + 1);
+        links.push([None; 4]);
+        links[parent][side as usize] = Some(idx);
+        links[idx][side.opposite() as usize] = Some(parent);
+    }
+
+    let exit = (0..cells.len()).max_by_key(|&i| depth[i]).unwrap();
+    let shop = if cells.len() >= MIN_ROOMS_FOR_SHOP {
+        let others: Vec<usize> = (1..cells.len()).filter(|&i| i != exit).collect();
+        others.choose(rng).cloned()
+    } else {
+        None
+    };
+
+    let mut last_level = last_level;
+    let rooms = links
+        .iter()
+        .enumerate()
+        .map(|(idx, neighbours)| {
+            let mut attempts = 0;
+            let (level, map, doors, counter) = loop {
+                // Generated rooms are always closed in by walls, so doors always fit
+                let (level, map) = if attempts < MAX_LEVEL_ATTEMPTS {
+                    pick_level(floor, levels, last_level, rng)
+                } else {
+                    (GENERATED_LEVEL, generate_level(rng.gen()))
+                };
+                attempts += 1;
+
+                // Shops lay their goods out where the teleport would have been
+                let counter = iproduct!(0..GAME_MAP_X, 0..GAME_MAP_Y)
+                    .find(|&(x, y)| map[x][y] == GameMapTile::Teleport);
+
+                if let (Some((map, doors)), Some(counter)) =
+                    (prepare_room(map, neighbours, idx == 0, idx == exit), counter)
+                {
+                    break (level, map, doors, counter);
+                }
+                warn!("Could not fit doors into level {}, picking another", level);
+            };
+            last_level = level;
+
+            let kind = if shop == Some(idx) {
+                RoomKind::Shop { counter }
+            } else {
+                RoomKind::Combat
+            };
+
+            let cleared = kind != RoomKind::Combat;
+            DungeonRoom {
+                map,
+                neighbours: *neighbours,
+                doors,
+                cleared,
+                is_exit: idx == exit,
+                level,
+                kind,
+                stock: None,
+                dropped: Vec::new(),
+                charged: cleared,
+            }
+        })
+        .collect();
+
+    DungeonFloor { rooms, current: 0 }
+}
+
+/// Cuts a door into `map` for every neighbour and removes the start/teleport tiles that
+/// don't belong in this room.
+fn prepare_room(
+    mut map: game_map_tiles,
+    neighbours: &[Option<usize>; 4],
+    is_entrance: bool,
+    is_exit: bool,
+) -> Option<(game_map_tiles, [Option<(usize, usize)>; 4])> {
+    let start = iproduct!(0..GAME_MAP_X, 0..GAME_MAP_Y)
+        .find(|&(x, y)| map[x][y] == GameMapTile::PlayerStart)?;
+    let reachable = reachable_tiles(&map, start);
+
+    let mut doors = [None; 4];
+    for side in DOOR_SIDES.iter() {
+        if neighbours[*side as usize].is_some() {
+            let (x, y) = find_door_spot(&map, &reachable, *side)?;
+            map[x][y] = GameMapTile::Door(*side);
+            doors[*side as usize] = Some((x, y));
+        }
+    }
+
+    for (x, y) in iproduct!(0..GAME_MAP_X, 0..GAME_MAP_Y) {
+        let tile = map[x][y];
+        match tile {
+            GameMapTile::PlayerStart if !is_entrance => map[x][y] = GameMapTile::Floor,
+            GameMapTile::Teleport if !is_exit => map[x][y] = GameMapTile::Floor,
+            _ => (),
+        }
+    }
+
+    Some((map, doors))
+}
+
+/// The outermost blocked tile on `side` with a reachable tile right behind it. Ties go to
+/// the spot closest to the middle of that wall.
+fn find_door_spot(
+    map: &game_map_tiles,
+    reachable: &[[bool; GAME_MAP_Y]; GAME_MAP_X],
+    side: DoorSide,
+) -> Option<(usize, usize)> {
+    let (dx, dy) = side.offset();
+
+    let candidates: Vec<(usize, usize)> = iproduct!(0..GAME_MAP_X, 0..GAME_MAP_Y)
+        .filter(|&(x, y)| match map[x][y] {
+            GameMapTile::Wall | GameMapTile::Nothing => true,
+            _ => false,
+        })
+        .filter(|&(x, y)| {
+            let (ix, iy) = (x as i32 - dx, y as i32 - dy);
+            ix >= 0
+                && iy >= 0
+                && (ix as usize) < GAME_MAP_X
+                && (iy as usize) < GAME_MAP_Y
+                && reachable[ix as usize][iy as usize]
+        })
+        .collect();
+
+    let outermost = candidates
+        .iter()
+        .map(|&(x, y)| x as i32 * dx + y as i32 * dy)
+        .max()?;
+    let mut on_edge: Vec<(usize, usize)> = candidates
+        .into_iter()
+        .filter(|&(x, y)| x as i32 * dx + y as i32 * dy == outermost)
+        .collect();
+
+    // Sorted along the wall, so the middle entry is the most central one
+    on_edge.sort_by_key(|&(x, y)| if dx != 0 { y } else { x });
+    Some(on_edge[on_edge.len() / 2])
+}
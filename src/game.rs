@@ -17,23 +17,48 @@ use itertools::{iproduct, Itertools};
 use rand::{
     distributions::{Distribution, Uniform},
     seq::SliceRandom,
-    thread_rng,
+    thread_rng, Rng,
 };
 
 use crate::audio::{initialise_audio, Music};
 use crate::maps;
 use crate::pause_screen::Paused;
 use crate::game_over_screen::GameOverScreen;
+use std::path::Path;
 use std::time::Duration;
-use crate::game_scale::{gen_enemy_list, get_enemy_time, get_enemy_bullet_speed};
+use amethyst::utils::application_root_dir;
+use crate::game_scale::{Difficulty, DifficultyCurve, DifficultyMode, DifficultyPresets};
 use std::iter::{Cycle, Iterator};
-use std::slice::Iter;
+use std::vec::IntoIter;
 use crate::maps::game_map_tiles;
+use crate::levels::{load_levels, LevelSet};
+use crate::dungeon::{generate_floor, DoorSide, DungeonFloor};
+use crate::run_rng::RunRng;
+use crate::pathfinding::Pathing;
+use crate::collision::CollisionLayer;
+use crate::aabb::Aabb;
+use crate::weapons::{Weapon, WeaponDefs, WeaponKind};
+use crate::items::ItemRegistry;
+use crate::actives::{ActiveItem, ActiveKind};
+use crate::relics::{RelicKind, Relics};
+use serde::{Deserialize, Serialize};
 
 pub const ARENA_HEIGHT: f32 = 768.0;
 pub const ARENA_WIDTH: f32 = 1366.0;
 
-pub struct Game;
+#[derive(Default)]
+pub struct Game {
+    seed: Option<u64>,
+    mode: DifficultyMode,
+}
+
+impl Game {
+    /// Starts a run from `seed`, or from a random one when `None`.
+    pub fn new(seed: Option<u64>, mode: DifficultyMode) -> Self {
+        Self { seed, mode }
+    }
+}
+
 impl<'a, 'b> State<PausableGameData<'a, 'b>, StateEvent> for Game {
     fn on_start(&mut self, data: StateData<PausableGameData>) {
         let world = data.world;
@@ -46,6 +71,27 @@ impl<'a, 'b> State<PausableGameData<'a, 'b>, StateEvent> for Game {
         world.register::<Item>();
         world.add_resource(FloorsVisited(0));
         world.add_resource(GameOver(false));
+
+        let seed = self.seed.unwrap_or_else(|| thread_rng().gen());
+        info!("Starting run with seed {}", seed);
+        world.add_resource(RunRng::new(seed));
+
+        let levels_path = format!("{}/resources/levels", application_root_dir());
+        world.add_resource(load_levels(Path::new(&levels_path)));
+
+        let presets: DifficultyPresets = load_resource("difficulty.ron");
+        let difficulty = presets.difficulty(self.mode);
+        world.add_resource(presets.get(&difficulty.preset).clone().scaled(&difficulty));
+        world.add_resource(difficulty);
+
+        let weapon_defs: WeaponDefs = load_resource("weapons.ron");
+        weapon_defs.validate();
+        world.add_resource(weapon_defs);
+
+        let registry: ItemRegistry = load_resource("items.ron");
+        registry.validate();
+        world.add_resource(registry);
+
         // Init Stuff
         let sprite_sheet = load_sprite_sheet(world);
         world.add_resource(LoadedSpriteSheet(Some(sprite_sheet.clone())));
@@ -56,7 +102,7 @@ impl<'a, 'b> State<PausableGameData<'a, 'b>, StateEvent> for Game {
         init_game_map(world, sprite_sheet.clone());
         init_player(world, sprite_sheet.clone());
         initialise_camera(world);
-        init_enemy(world, sprite_sheet.clone());
+        init_enemies(world);
         init_ui(world);
         initialise_audio(world);
     }
@@ -83,7 +129,7 @@ impl<'a, 'b> State<PausableGameData<'a, 'b>, StateEvent> for Game {
         &mut self,
         data: StateData<PausableGameData>,
     ) -> Trans<PausableGameData<'a, 'b>, StateEvent> {
-        data.data.update(&data.world, true);
+        data.data.update(data.world, true);
 
         if data.world.read_resource::<GameOver>().0 {
             Trans::Switch(Box::new(GameOverScreen))
@@ -99,10 +145,17 @@ pub struct GameOver(pub bool);
 #[storage(VecStorage)]
 pub struct Player {
     pub current_direction: ShootDirection,
+    /// Velocity over the last frame, in units per second
     pub speed: [f32; 2],
     pub invincible_time: Duration,
     pub damage: u64,
     pub speed_multi: f32,
+    /// Time until the player can dash again
+    pub dash_cooldown: Duration,
+    /// Time left in the current dash, zero when not dashing
+    pub dash_time: Duration,
+    /// Velocity of the current dash, in units per second
+    pub dash_velocity: [f32; 2],
 }
 
 impl Default for Player {
@@ -112,7 +165,10 @@ impl Default for Player {
             speed: Default::default(),
             invincible_time: Default::default(),
             damage: 10,
-            speed_multi: 4.,
+            speed_multi: 240.,
+            dash_cooldown: Default::default(),
+            dash_time: Default::default(),
+            dash_velocity: Default::default(),
         }
     }
 }
@@ -124,7 +180,7 @@ impl Player {
                 self.damage += 10;
             }
             ItemType::PowerUp(PowerUps::Speed) => {
-                self.speed_multi += 1.0;
+                self.speed_multi += 60.;
                 warn!("New speed: {:?}", self.speed_multi);
             }
             _ => (),
@@ -157,6 +213,19 @@ impl GamePosition {
         )
     }
 
+    /// Whether the position is still inside the area covered by the map's tiles
+    pub fn in_map(&self) -> bool {
+        self.0[0] >= 0.
+            && self.0[1] >= 0.
+            && self.0[0] < (GAME_MAP_X as u32 * TILE_SIZE) as f32
+            && self.0[1] < (GAME_MAP_Y as u32 * TILE_SIZE) as f32
+    }
+
+    /// Tile under the middle of the entity's collision box
+    pub fn center_tile(&self, coll_flag: &CollisionDetectionFlag) -> (usize, usize) {
+        GamePosition(Aabb::of(self, coll_flag).center).to_tile()
+    }
+
     pub fn from_tile((x, y): (usize, usize)) -> Self {
         let x_gpos = (x as f32) * TILE_SIZE as f32;
         let y_gpos = (y as f32) * TILE_SIZE as f32;
@@ -170,7 +239,19 @@ impl Default for GamePosition {
     }
 }
 
-fn load_sprite_sheet(world: &mut World) -> SpriteSheetHandle {
+/// Where the entity was at the start of the last fixed tick, to draw it between ticks
+#[derive(Component, Debug, Clone, Copy)]
+#[storage(DenseVecStorage)]
+pub struct PreviousGamePosition(pub [f32; 2]);
+
+/// Reads `resources/<name>`. The game has nothing to fall back on when one of these is
+/// missing or broken, so it stops here with the reason.
+fn load_resource<T: Config>(name: &str) -> T {
+    let path = format!("{}/resources/{}", application_root_dir(), name);
+    T::load_no_fallback(&path).unwrap_or_else(|err| panic!("Could not load {}: {:?}", path, err))
+}
+
+pub fn load_sprite_sheet(world: &mut World) -> SpriteSheetHandle {
     // Load the sprite sheet necessary to render the graphics.
     // The texture is the pixel data
     // `texture_handle` is a cloneable reference to the texture
@@ -197,7 +278,7 @@ fn load_sprite_sheet(world: &mut World) -> SpriteSheetHandle {
     )
 }
 
-fn load_fonts(world: &mut World) -> (Handle<FontAsset>, Handle<FontAsset>) {
+pub fn load_fonts(world: &mut World) -> (Handle<FontAsset>, Handle<FontAsset>) {
     let font1 = world.read_resource::<Loader>().load(
         "fonts/NanumPenScript-Regular.ttf",
         TtfFormat,
@@ -236,6 +317,7 @@ pub fn initialise_camera(world: &mut World) {
 pub struct Shooter {
     pub shoot_rate: Duration,
     pub current_cooldown: Duration,
+    /// Bullet speed in units per second
     pub speed: f32,
     upgrades: usize,
 }
@@ -243,9 +325,8 @@ pub struct Shooter {
 const SHOOTER_UPGRADES_MS: [u64; 11] = [200, 200, 100, 100, 100, 50, 50, 50, 25, 25, 25];
 
 impl Shooter {
-    pub fn new(rate: u64, speed: f32) -> Self {
-        let mut rng = thread_rng();
-        let dither = Uniform::new_inclusive(0, 300).sample(&mut rng);
+    pub fn new(rate: u64, speed: f32, rng: &mut RunRng) -> Self {
+        let dither = Uniform::new_inclusive(0, 300).sample(rng);
         Self {
             shoot_rate: Duration::from_secs(rate),
             current_cooldown: Duration::from_secs(rate) + Duration::from_millis(dither),
@@ -265,7 +346,7 @@ impl Shooter {
                 }
             }
             ItemType::PowerUp(PowerUps::ProjectileSpeed) => {
-                self.speed += 2.;
+                self.speed += 120.;
             }
             _ => (),
         };
@@ -290,18 +371,29 @@ fn init_player(world: &mut World, sprite_sheet: SpriteSheetHandle) {
 
     let mut game_pos = GamePosition::from_tile(start); //TODO: Start according to map
 
+    let starting_time = world.read_resource::<Difficulty>().starting_time;
+
+    let (shooter, anim) = {
+        let mut rng = world.write_resource::<RunRng>();
+        (Shooter::new(1, 300., &mut rng), Animation::new(1000, PLAYER_FRAMES, &mut rng))
+    };
+
     // Create a left plank entity.
     let ent = world
         .create_entity()
         .with(Player::default())
-        .with(Shooter::new(1, 5.))
+        .with(shooter)
+        .with(Weapon::default())
+        .with(Relics::default())
+        .with(ActiveItem::default())
+        .with(CollisionLayer::player())
         .with(game_pos)
         .with(transform)
         .with(sprite_render.clone())
         .with(Transparent)
         .with(CollisionDetectionFlag([45., 45.]))
-        .with(TimeLeft::new(180))
-        .with(Animation::new(1000, PLAYER_FRAMES))
+        .with(TimeLeft::new(starting_time))
+        .with(anim)
         .build();
 
     world.add_resource(PlayerEntity(Some(ent)));
@@ -316,6 +408,8 @@ pub enum GameMapTile {
     Nothing,
     PlayerStart,
     Teleport,
+    /// Leads to the neighbouring room on that side, locked until the room is cleared
+    Door(DoorSide),
 }
 
 impl Default for GameMapTile {
@@ -328,6 +422,9 @@ pub const GAME_MAP_X: usize = 32;
 pub const GAME_MAP_Y: usize = 32;
 pub const TILE_SIZE: u32 = 60;
 
+/// `GameMap::current_level` of a room that came from `procgen` rather than the `LevelSet`
+pub const GENERATED_LEVEL: usize = std::usize::MAX;
+
 #[derive(Debug, Default)]
 pub struct GameMap {
     pub map: maps::game_map_tiles,
@@ -336,6 +433,9 @@ pub struct GameMap {
     pub ending_spot: (usize, usize),
     pub current_level: usize,
     pub powerups_spawned: bool,
+    /// Whether the shop stock and dropped items of this room have been put out yet
+    pub items_spawned: bool,
+    pub doors_locked: bool,
 }
 
 impl GameMap {
@@ -347,77 +447,129 @@ impl GameMap {
             ending_spot: (GAME_MAP_X - 1, GAME_MAP_Y - 1),
             current_level,
             powerups_spawned: false,
+            items_spawned: false,
+            doors_locked: false,
+        }
+    }
+
+    /// Whether the tile stops players and enemies from walking onto it
+    pub fn blocks(&self, (x, y): (usize, usize)) -> bool {
+        match self.map[x][y] {
+            GameMapTile::Wall => true,
+            GameMapTile::Door(_) => self.doors_locked,
+            _ => false,
         }
     }
+
+    /// Walks a Bresenham line between the two tiles, false if any tile after `from` blocks it
+    pub fn has_line_of_sight(&self, from: (usize, usize), to: (usize, usize)) -> bool {
+        let (mut x, mut y) = (from.0 as i32, from.1 as i32);
+        let (x1, y1) = (to.0 as i32, to.1 as i32);
+        let dx = (x1 - x).abs();
+        let dy = -(y1 - y).abs();
+        let sx = if x < x1 { 1 } else { -1 };
+        let sy = if y < y1 { 1 } else { -1 };
+        let mut err = dx + dy;
+
+        while (x, y) != (x1, y1) {
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+
+            if self.blocks((x as usize, y as usize)) {
+                return false;
+            }
+        }
+        true
+    }
 }
 
 fn init_game_map(world: &mut World, sprite_sheet: SpriteSheetHandle) {
-    let mut truemap = GameMap::new(maps::turn_into_game_map(maps::LEVEL2), 1);
-
-    let sprite_wall = SpriteRender {
-        sprite_sheet: sprite_sheet.clone(),
-        sprite_number: 43, // paddle is the first sprite in the sprite_sheet
+    let dungeon = {
+        let levels = world.read_resource::<LevelSet>();
+        let mut rng = world.write_resource::<RunRng>();
+        generate_floor(0, &levels, GENERATED_LEVEL, &mut *rng)
     };
 
-    let sprite_floor = SpriteRender {
-        sprite_sheet: sprite_sheet.clone(),
-        sprite_number: 13, // paddle is the first sprite in the sprite_sheet
-    };
-    let sprite_other_floor = SpriteRender {
-        sprite_sheet: sprite_sheet.clone(),
-        sprite_number: 14, // paddle is the first sprite in the sprite_sheet
-    };
+    let mut truemap = GameMap::new(dungeon.current_room().map, dungeon.current_room().level);
+    truemap.doors_locked = true;
 
     for (x, y) in iproduct!(0..GAME_MAP_X, 0..GAME_MAP_Y) {
         let game_pos = GamePosition::from_tile((x, y));
         let mut transform = Transform::default();
         transform.set_z(-1.0);
-        let mut builder = world
+        let builder = world
             .create_entity()
             .with(transform)
             .with(game_pos)
             .with(RoomFlag);
 
-        match truemap.map[x][y] {
-            GameMapTile::Wall => {
+        build_tile(builder, (x, y), &mut truemap, &sprite_sheet).build();
+    }
 
-                let (s_num, flip) = dbg!(get_wall_sprite_num((x,y), &truemap));
+    warn!("{:?}", truemap.player_start);
 
-                let sprite = SpriteRender {
-                    sprite_sheet: sprite_sheet.clone(),
-                    sprite_number: s_num, // paddle is the first sprite in the sprite_sheet
-                };
+    world.add_resource(truemap);
+    world.add_resource(dungeon);
+}
 
-                builder = builder
-                    .with(sprite.clone())
-                    .with(CollisionDetectionFlag([60.0, 60.0]))
-                    .with(WallFlag);
+/// Adds the sprite and collision for the tile at `(x, y)` and records spawn points in `gamemap`
+fn build_tile<B: Builder>(
+    mut builder: B,
+    (x, y): (usize, usize),
+    gamemap: &mut GameMap,
+    sprite_sheet: &SpriteSheetHandle,
+) -> B {
+    let sprite = |sprite_number| SpriteRender {
+        sprite_sheet: sprite_sheet.clone(),
+        sprite_number,
+    };
 
-                if flip {
-                    builder = builder.with(Flipped::Horizontal);
-                }
-            }
-            GameMapTile::Floor => {
-                builder = builder.with(sprite_floor.clone());
-                truemap.valid_enemy_spawns.push((x, y));
-            }
-            GameMapTile::PlayerStart => {
-                builder = builder.with(sprite_floor.clone());
-                truemap.player_start = (x, y);
-            }
-            GameMapTile::Teleport => {
-                builder = builder.with(sprite_other_floor.clone());
-                truemap.ending_spot = (x, y);
-            }
-            _ => (),
-        };
+    let tile = gamemap.map[x][y];
+    match tile {
+        GameMapTile::Wall => {
+            let (s_num, flip) = dbg!(get_wall_sprite_num((x,y), &gamemap));
 
-        builder.build();
-    }
+            builder = builder
+                .with(sprite(s_num))
+                .with(WallFlag);
 
-    warn!("{:?}", truemap.player_start);
+            if flip {
+                builder = builder.with(Flipped::Horizontal);
+            }
+        }
+        GameMapTile::Floor => {
+            builder = builder.with(sprite(13));
+            gamemap.valid_enemy_spawns.push((x, y));
+        }
+        GameMapTile::PlayerStart => {
+            builder = builder.with(sprite(13));
+            gamemap.player_start = (x, y);
+        }
+        GameMapTile::Teleport => {
+            builder = builder.with(sprite(14));
+            gamemap.ending_spot = (x, y);
+        }
+        GameMapTile::Door(_) => {
+            builder = builder.with(DoorFlag);
+            if gamemap.doors_locked {
+                builder = builder
+                    .with(sprite(DOOR_LOCKED_SPRITE))
+                    .with(WallFlag);
+            } else {
+                builder = builder.with(sprite(DOOR_OPEN_SPRITE));
+            }
+        }
+        GameMapTile::Nothing => (),
+    };
 
-    world.add_resource(truemap);
+    builder
 }
 
 #[derive(Component, Default)]
@@ -428,6 +580,13 @@ pub struct WallFlag;
 #[storage(NullStorage)]
 pub struct RoomFlag;
 
+#[derive(Component, Default)]
+#[storage(NullStorage)]
+pub struct DoorFlag;
+
+pub const DOOR_LOCKED_SPRITE: usize = 52;
+pub const DOOR_OPEN_SPRITE: usize = 14;
+
 #[derive(Component, Default, Clone, Copy)]
 #[storage(DenseVecStorage)]
 pub struct CollisionDetectionFlag(pub [f32; 2]);
@@ -478,52 +637,98 @@ impl TimeLeft {
 #[storage(VecStorage)]
 pub struct EnemyFlag {
     pub moves: bool,
+    /// Units per second
+    pub speed: f32,
 }
 
 impl EnemyFlag {
+    pub fn new(speed: f32) -> Self {
+        Self {
+            moves: true,
+            speed,
+        }
+    }
+
     pub fn new_stationary() -> Self {
         Self {
             moves: false,
+            speed: 0.,
         }
     }
 }
 
 impl Default for EnemyFlag {
     fn default() -> Self {
-        Self {
-            moves: true,
-        }
+        Self::new(120.)
     }
 }
 
-pub struct NumEnemiesLeft(pub u32);
+/// What kind of bullets a shooting enemy fires
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+#[storage(VecStorage)]
+pub enum AttackPattern {
+    Aimed,
+    Homing,
+    Ricochet,
+}
 
-fn init_enemy(world: &mut World, sprite_sheet: SpriteSheetHandle) {
-    let sprite_render = SpriteRender {
-        sprite_sheet: sprite_sheet.clone(),
-        sprite_number: 0, // paddle is the first sprite in the sprite_sheet
-    };
+impl Default for AttackPattern {
+    fn default() -> Self {
+        AttackPattern::Aimed
+    }
+}
 
-    let mut transform = Transform::default();
-    transform.set_z(-0.5);
+impl AttackPattern {
+    /// Only plain shots on the first floors, after that a mix
+    pub fn get_random(floor: u32, rng: &mut RunRng) -> Self {
+        use self::AttackPattern::*;
+        if floor < 2 {
+            Aimed
+        } else {
+            *[Aimed, Aimed, Homing, Ricochet].choose(rng).unwrap()
+        }
+    }
+}
 
-    let start = world.read_resource::<GameMap>().valid_enemy_spawns[0];
+/// Extra velocity from being shoved, in units per second. Dies down on its own.
+#[derive(Component, Default, Debug)]
+#[storage(DenseVecStorage)]
+pub struct Knockback {
+    pub velocity: [f32; 2],
+}
 
-    let mut game_pos = GamePosition::from_tile(start); //TODO: Start according to map
+impl Knockback {
+    /// Adds `impulse`, giving the entity the component if it hasn't been shoved before
+    pub fn push(store: &mut WriteStorage<Knockback>, ent: Entity, impulse: [f32; 2]) {
+        if let Some(knockback) = store.get_mut(ent) {
+            knockback.velocity[0] += impulse[0];
+            knockback.velocity[1] += impulse[1];
+            return;
+        }
+        store.insert(ent, Knockback { velocity: impulse }).unwrap();
+    }
+}
 
-    world
-        .create_entity()
-        .with(EnemyFlag::default())
-        .with(game_pos)
-        .with(transform)
-        .with(sprite_render.clone())
-        .with(Transparent)
-        .with(CollisionDetectionFlag([45., 45.]))
-        .with(TimeLeft::new(30))
-        .with(Shooter::new(2, 10.))
-        .build();
+pub struct NumEnemiesLeft(pub u32);
 
-    world.write_resource::<NumEnemiesLeft>().0 += 1;
+/// Spawns the first room's enemies the same way `enter_room` does for every room after it
+fn init_enemies(world: &mut World) {
+    {
+        let mut gamemap = world.write_resource::<GameMap>();
+        let start = gamemap.player_start;
+        keep_spawns_away(&mut gamemap, start);
+    }
+
+    Enemies::spawn(
+        &world.entities(),
+        &world.read_resource::<LazyUpdate>(),
+        &world.read_resource::<LoadedSpriteSheet>(),
+        &world.read_resource::<GameMap>(),
+        &mut world.write_resource::<NumEnemiesLeft>(),
+        &world.read_resource::<FloorsVisited>(),
+        &world.read_resource::<DifficultyCurve>(),
+        &mut world.write_resource::<RunRng>(),
+    );
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -545,100 +750,124 @@ impl Default for ShootDirection {
     }
 }
 
+impl ShootDirection {
+    /// Unit vector pointing in this direction, zero for `None`
+    pub fn to_vector(&self) -> [f32; 2] {
+        use self::ShootDirection::*;
+        match self {
+            Left => [-1., 0.],
+            Right => [1., 0.],
+            Up => [0., 1.],
+            Down => [0., -1.],
+            UpLeft => [-0.707, 0.707],
+            UpRight => [0.707, 0.707],
+            DownLeft => [-0.707, -0.707],
+            DownRight => [0.707, -0.707],
+            None => [0., 0.],
+        }
+    }
+}
+
 pub fn start_new_level(
-    mut gamemap: &mut GameMap,
-    mut game_poses: WriteStorage<GamePosition>,
+    gamemap: &mut GameMap,
+    dungeon: &mut DungeonFloor,
+    game_poses: WriteStorage<GamePosition>,
     player: Entity,
     sprite_sheet: &LoadedSpriteSheet,
     num_enemies: &mut NumEnemiesLeft,
     entities: Entities,
     lazy: &LazyUpdate,
     floors: &FloorsVisited,
+    levels: &LevelSet,
+    difficulty: &DifficultyCurve,
+    rng: &mut RunRng,
 ) {
-    let sprite_wall = SpriteRender {
-        sprite_sheet: sprite_sheet.0.clone().unwrap(),
-        sprite_number: 43, // paddle is the first sprite in the sprite_sheet
-    };
-
-    let sprite_floor = SpriteRender {
-        sprite_sheet: sprite_sheet.0.clone().unwrap(),
-        sprite_number: 13, // paddle is the first sprite in the sprite_sheet
-    };
-    let sprite_other_floor = SpriteRender {
-        sprite_sheet: sprite_sheet.0.clone().unwrap(),
-        sprite_number: 14, // paddle is the first sprite in the sprite_sheet
-    };
-
-    let mut rng = thread_rng();
-    let mut choice = Uniform::new(0, maps::NUM_LEVELS).sample(&mut rng);
-    while choice == gamemap.current_level {
-        choice = Uniform::new(0, maps::NUM_LEVELS).sample(&mut rng);
-    }
+    *dungeon = generate_floor(floors.0, levels, gamemap.current_level, rng);
+
+    enter_room(
+        gamemap,
+        dungeon,
+        0,
+        None,
+        game_poses,
+        player,
+        sprite_sheet,
+        num_enemies,
+        entities,
+        lazy,
+        floors,
+        difficulty,
+        rng,
+    );
+}
 
-    let level = maps::turn_into_game_map(maps::LEVELS[choice]);
+/// Builds room `room` of the current floor and moves the player into it, next to the door on
+/// side `via` or onto the player start when coming from the previous floor.
+pub fn enter_room(
+    gamemap: &mut GameMap,
+    dungeon: &mut DungeonFloor,
+    room: usize,
+    via: Option<DoorSide>,
+    mut game_poses: WriteStorage<GamePosition>,
+    player: Entity,
+    sprite_sheet: &LoadedSpriteSheet,
+    num_enemies: &mut NumEnemiesLeft,
+    entities: Entities,
+    lazy: &LazyUpdate,
+    floors: &FloorsVisited,
+    difficulty: &DifficultyCurve,
+    rng: &mut RunRng,
+) {
+    dungeon.current = room;
+    let room = dungeon.current_room();
 
-    gamemap.current_level = choice;
-    gamemap.map = level;
+    gamemap.current_level = room.level;
+    gamemap.map = room.map;
     gamemap.valid_enemy_spawns.clear();
-    gamemap.powerups_spawned = false;
+    gamemap.ending_spot = (GAME_MAP_X - 1, GAME_MAP_Y - 1);
+    gamemap.doors_locked = !room.cleared;
+    // The reward next to the teleport only drops the first time the exit room is cleared
+    gamemap.powerups_spawned = room.cleared || !room.is_exit;
+    gamemap.items_spawned = false;
 
+    let sheet = sprite_sheet.0.clone().unwrap();
     for (x, y) in iproduct!(0..GAME_MAP_X, 0..GAME_MAP_Y) {
         let game_pos = GamePosition::from_tile((x, y));
         let mut transform = Transform::default();
         transform.set_z(-1.0);
-        let mut builder = lazy
+        let builder = lazy
             .create_entity(&entities)
             .with(transform)
             .with(game_pos)
             .with(RoomFlag);
 
-        match gamemap.map[x][y] {
-            GameMapTile::Wall => {
-                let (s_num, flip) = dbg!(get_wall_sprite_num((x,y), &gamemap));
-
-                let sprite = SpriteRender {
-                    sprite_sheet: sprite_sheet.0.clone().unwrap(),
-                    sprite_number: s_num, // paddle is the first sprite in the sprite_sheet
-                };
-
-                builder = builder
-                    .with(sprite.clone())
-                    .with(CollisionDetectionFlag([60.0, 60.0]))
-                    .with(WallFlag);
-
-                if flip {
-                    builder = builder.with(Flipped::Horizontal);
-                }
-            }
-            GameMapTile::Floor => {
-                builder = builder.with(sprite_floor.clone());
-                gamemap.valid_enemy_spawns.push((x, y));
-            }
-            GameMapTile::PlayerStart => {
-                builder = builder.with(sprite_floor.clone());
-                gamemap.player_start = (x, y);
+        build_tile(builder, (x, y), gamemap, &sheet).build();
+    }
 
-                let player_pos = game_poses.get_mut(player).unwrap();
+    let start = match via {
+        Some(side) => room.entry_tile(side).unwrap_or(gamemap.player_start),
+        None => gamemap.player_start,
+    };
 
-                warn!("Setting start: {:?} {:?}", (x, y), choice);
-                warn!("{:?}", player_pos);
+    let player_pos = game_poses.get_mut(player).unwrap();
+    warn!("Entering room {} at {:?}", dungeon.current, start);
+    player_pos.0 = GamePosition::from_tile(start).0;
 
-                let new_pos = GamePosition::from_tile((x, y));
-                player_pos.0[0] = new_pos.0[0];
-                player_pos.0[1] = new_pos.0[1];
-                warn!("{:?}", player_pos);
-            }
-            GameMapTile::Teleport => {
-                builder = builder.with(sprite_other_floor.clone());
-                gamemap.ending_spot = (x, y);
-            }
-            _ => (),
-        };
-
-        builder.build();
+    if !room.cleared {
+        keep_spawns_away(gamemap, start);
+        Enemies::spawn(&entities, lazy, sprite_sheet, gamemap, num_enemies, floors, difficulty, rng);
     }
+}
 
-    Enemies::spawn(&entities, lazy, sprite_sheet, gamemap, num_enemies, floors);
+/// Don't drop enemies right on top of the player walking in, unless the room has nowhere else
+fn keep_spawns_away(gamemap: &mut GameMap, start: (usize, usize)) {
+    let near_spawns = gamemap.valid_enemy_spawns.clone();
+    gamemap.valid_enemy_spawns.retain(|&(x, y)| {
+        (x as i32 - start.0 as i32).abs().max((y as i32 - start.1 as i32).abs()) > 3
+    });
+    if gamemap.valid_enemy_spawns.is_empty() {
+        gamemap.valid_enemy_spawns = near_spawns;
+    }
 }
 
 pub enum Enemies {
@@ -655,19 +884,19 @@ impl Enemies {
         gamemap: &GameMap,
         num_enemies: &mut NumEnemiesLeft,
         floors: &FloorsVisited,
+        difficulty: &DifficultyCurve,
+        rng: &mut RunRng,
     ) {
 
 
-        for enem in gen_enemy_list(floors.0).iter() {
+        for enem in difficulty.gen_enemy_list(floors.0, rng).iter() {
             let mut transform = Transform::default();
             transform.set_z(-0.5);
 
-            let mut rng = thread_rng();
-
             let start = *gamemap
                 .valid_enemy_spawns
                 .as_slice()
-                .choose(&mut rng)
+                .choose(rng)
                 .unwrap();
 
             warn!("Spawn enemy here: {:?}", start);
@@ -677,8 +906,8 @@ impl Enemies {
                 .with(game_pos)
                 .with(transform)
                 .with(Transparent)
-
-                .with(TimeLeft::new(get_enemy_time(floors.0)));
+                .with(CollisionLayer::enemy())
+                .with(TimeLeft::new(difficulty.get_enemy_time(floors.0)));
 
             use self::Enemies::*;
             match enem {
@@ -691,8 +920,9 @@ impl Enemies {
                     builder.with(EnemyFlag::new_stationary())
                         .with(sprite_render.clone())
                         .with(CollisionDetectionFlag([60., 100.]))
-                        .with(Shooter::new(2, get_enemy_bullet_speed(floors.0)))
-                        .with(Animation::new(500, WALL_CLOCK_FRAMES))
+                        .with(Shooter::new(2, difficulty.get_enemy_bullet_speed(floors.0), rng))
+                        .with(AttackPattern::get_random(floors.0, rng))
+                        .with(Animation::new(500, WALL_CLOCK_FRAMES, rng))
                         .build();
                 }
                 NoShoot => {
@@ -700,7 +930,8 @@ impl Enemies {
                         sprite_sheet: sprite_sheet.0.clone().unwrap(),
                         sprite_number: 0, // paddle is the first sprite in the sprite_sheet
                     };
-                    builder.with(EnemyFlag::default())
+                    builder.with(EnemyFlag::new(difficulty.get_enemy_speed(floors.0)))
+                        .with(Pathing::default())
                         .with(sprite_render.clone())
                         .with(CollisionDetectionFlag([45., 45.]))
                         .build();
@@ -710,10 +941,13 @@ impl Enemies {
                         sprite_sheet: sprite_sheet.0.clone().unwrap(),
                         sprite_number: 0, // paddle is the first sprite in the sprite_sheet
                     };
-                    builder.with(EnemyFlag::default())
+                    builder.with(EnemyFlag::new(difficulty.get_enemy_speed(floors.0)))
+                        .with(Pathing::default())
                         .with(sprite_render.clone())
                         .with(CollisionDetectionFlag([45., 45.]))
-                        .with(Shooter::new(2, get_enemy_bullet_speed(floors.0))).build();
+                        .with(Shooter::new(2, difficulty.get_enemy_bullet_speed(floors.0), rng))
+                        .with(AttackPattern::get_random(floors.0, rng))
+                        .build();
                 }
             }
 
@@ -763,9 +997,102 @@ fn init_ui(world: &mut World) {
         .build();
 
     world.add_resource(UIEnts(ent));
+
+    let (transform, uitext) = {
+        let loaded_fonts = world.read_resource::<LoadedFonts>();
+
+        let transform = UiTransform::new(
+            "tooltip".to_string(),
+            Anchor::BottomLeft,
+            0.,
+            0.,
+            1.,
+            400.,
+            20.,
+            0,
+        );
+
+        let uitext = UiText::new(
+            loaded_fonts.0.clone(),
+            String::new(),
+            [1., 1., 0.6, 1.],
+            18.,
+        );
+
+        (transform, uitext)
+    };
+
+    let ent = world.create_entity().with(transform).with(uitext).build();
+
+    world.add_resource(Tooltip(ent));
+
+    let (transform, uitext) = {
+        let loaded_fonts = world.read_resource::<LoadedFonts>();
+
+        let transform = UiTransform::new(
+            "relics".to_string(),
+            Anchor::TopLeft,
+            220.,
+            -20.,
+            1.,
+            400.,
+            20.,
+            0,
+        );
+
+        let uitext = UiText::new(
+            loaded_fonts.0.clone(),
+            String::new(),
+            [1., 1., 1., 1.],
+            18.,
+        );
+
+        (transform, uitext)
+    };
+
+    let ent = world.create_entity().with(transform).with(uitext).build();
+
+    world.add_resource(RelicsHud(ent));
+
+    let (transform, uitext) = {
+        let loaded_fonts = world.read_resource::<LoadedFonts>();
+
+        let transform = UiTransform::new(
+            "active_item".to_string(),
+            Anchor::TopLeft,
+            220.,
+            -45.,
+            1.,
+            400.,
+            20.,
+            0,
+        );
+
+        let uitext = UiText::new(
+            loaded_fonts.0.clone(),
+            String::new(),
+            [1., 1., 1., 1.],
+            18.,
+        );
+
+        (transform, uitext)
+    };
+
+    let ent = world.create_entity().with(transform).with(uitext).build();
+
+    world.add_resource(ActiveHud(ent));
 }
 
-#[derive(Clone, Copy, Debug)]
+/// The text describing the item the player is standing next to
+pub struct Tooltip(pub Entity);
+
+/// The text listing the relics the player holds
+pub struct RelicsHud(pub Entity);
+
+/// The text showing the player's active item and its charge
+pub struct ActiveHud(pub Entity);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum PowerUps {
     Speed,
     ShootRate,
@@ -773,16 +1100,6 @@ pub enum PowerUps {
     Damage,
 }
 
-impl PowerUps {
-    pub fn get_random() -> Self {
-        let mut rng = thread_rng();
-        use self::PowerUps::*;
-        *[Speed, ShootRate, ProjectileSpeed, Damage]
-            .choose(&mut rng)
-            .unwrap()
-    }
-}
-
 #[derive(Component, Clone, Copy, Debug)]
 #[storage(VecStorage)]
 pub struct Item {
@@ -797,41 +1114,29 @@ impl Item {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+/// Marks an item in a shop, bought with the buy key instead of by walking over it
+#[derive(Component, Default)]
+#[storage(NullStorage)]
+pub struct ForSale;
+
+/// Marks an item the player just put down, it can't be picked up until they step off it
+#[derive(Component, Default)]
+#[storage(NullStorage)]
+pub struct Dropped;
+
+/// A UI text floating over the item it belongs to
+#[derive(Component)]
+#[storage(VecStorage)]
+pub struct ItemLabel(pub Entity);
+
+/// What an item does, also its key in the `ItemRegistry`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ItemType {
     PowerUp(PowerUps),
     PlusTime(u64),
-}
-
-impl ItemType {
-    pub fn to_sprite_sheet_num(&self) -> usize {
-        use self::ItemType::*;
-        match self {
-            PowerUp(PowerType) => {
-                match PowerType {
-                    PowerUps::Speed => 36,
-                    PowerUps::Damage => 1,
-                    PowerUps::ProjectileSpeed => 24,
-                    PowerUps::ShootRate => 30,
-                }
-            }
-            PlusTime(_) => {
-                11
-            }
-        }
-    }
-
-    pub fn get_anim(&self) -> Animation {
-        use self::ItemType::*;
-        use self::PowerUps::*;
-        match self {
-            PowerUp(Speed) => Animation::new(200, SPEED_POWER_FRAMES),
-            PowerUp(Damage) => Animation::new(200, DAMAGE_POWER_FRAMES),
-            PowerUp(ProjectileSpeed) => Animation::new(200, PROJ_POWER_FRAMES),
-            PowerUp(ShootRate) => Animation::new(200, SHOOT_FAST_POWER_FRAMES),
-            PlusTime(_) => Animation::new(200, MOAR_TIME_FRAMES)
-        }
-    }
+    Weapon(WeaponKind),
+    Relic(RelicKind),
+    Active(ActiveKind),
 }
 
 #[derive(Component)]
@@ -839,32 +1144,27 @@ impl ItemType {
 pub struct Animation {
     pub time_per_frame: Duration,
     pub cooldown: Duration,
-    pub sprites: Cycle<Iter<'static, usize>>,
+    pub sprites: Cycle<IntoIter<usize>>,
 }
 
 impl Animation {
-    pub fn new(time_per_frame: u64, sprites: &'static [usize]) -> Self {
+    pub fn new(time_per_frame: u64, sprites: &[usize], rng: &mut RunRng) -> Self {
         let cooldown = Duration::from_millis(
-            Uniform::new(0,time_per_frame).sample(&mut thread_rng())
+            Uniform::new(0,time_per_frame).sample(rng)
         );
 
         Self {
             time_per_frame: Duration::from_millis(time_per_frame),
             cooldown,
-            sprites: sprites.iter().cycle()
+            sprites: sprites.to_vec().into_iter().cycle()
         }
     }
 }
 
 pub const PLAYER_FRAMES: &[usize] = &[16, 17, 18, 18];
-pub const DAMAGE_POWER_FRAMES: &[usize] = &[1,2,3,4,5,6];
 pub const DEAD_ENEMY_FRAMES: &[usize] = &[7,8,9];
 pub const PLAYER_INVUL_FRAMES: &[usize] = &[20,21,22,23];
-pub const PROJ_POWER_FRAMES: &[usize] = &[24,25,26,27,28,29];
-pub const SHOOT_FAST_POWER_FRAMES: &[usize] = &[30,31,32,33,34,35];
-pub const SPEED_POWER_FRAMES: &[usize] = &[36,37,38,39,40,41];
 pub const WALL_CLOCK_FRAMES: &[usize] = &[45, 46, 47, 48];
-pub const MOAR_TIME_FRAMES: &[usize] = &[11,11,12];
 
 
 fn get_wall_sprite_num((x,y): (usize, usize), gamemap: &GameMap) -> (usize, bool) {
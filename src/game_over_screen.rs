@@ -9,6 +9,12 @@ use amethyst::ui::{Anchor, TtfFormat, UiText, UiTransform};
 use amethyst::core::Transform;
 
 use crate::game::{Game, LoadedSpriteSheet, LoadedFonts, ARENA_WIDTH, ARENA_HEIGHT, FloorsVisited};
+use crate::run_rng::RunRng;
+use crate::game_scale::{Difficulty, DifficultyMode};
+use crate::title_screen::TitleScreen;
+use amethyst::utils::application_root_dir;
+use std::fs::OpenOptions;
+use std::io::Write;
 
 pub struct GameOverScreen;
 impl<'a, 'b> State<PausableGameData<'a, 'b>, StateEvent> for GameOverScreen {
@@ -89,6 +95,35 @@ impl<'a, 'b> State<PausableGameData<'a, 'b>, StateEvent> for GameOverScreen {
             70.,
         );
 
+        world.create_entity()
+            .with(transform)
+            .with(uitext)
+            .build();
+
+        let seed = world.read_resource::<RunRng>().seed;
+        let mode = world.read_resource::<Difficulty>().mode;
+
+        record_score(floors, mode, seed);
+
+        let transform = UiTransform::new(
+            "seed".to_string(),
+            Anchor::TopLeft,
+            700.,
+            -630.,
+            0.95,
+            500.,
+            40.,
+            0,
+        );
+        let uitext = UiText::new(
+            {
+                world.read_resource::<LoadedFonts>().0.clone()
+            },
+            format!("{:?} - Seed: {} (R to replay)", mode, seed),
+            [1., 1., 1., 1.],
+            40.,
+        );
+
         world.create_entity()
             .with(transform)
             .with(uitext)
@@ -110,7 +145,11 @@ impl<'a, 'b> State<PausableGameData<'a, 'b>, StateEvent> for GameOverScreen {
                 Trans::Quit
             } else if is_key_down(&event, VirtualKeyCode::Space) {
                 // Delete pause screen stuff
-                Trans::Switch(Box::new(Game))
+                Trans::Switch(Box::new(TitleScreen::new(None)))
+            } else if is_key_down(&event, VirtualKeyCode::R) {
+                let seed = data.world.read_resource::<RunRng>().seed;
+                let mode = data.world.read_resource::<Difficulty>().mode;
+                Trans::Switch(Box::new(Game::new(Some(seed), mode)))
             } else {
                 Trans::None
             }
@@ -123,7 +162,23 @@ impl<'a, 'b> State<PausableGameData<'a, 'b>, StateEvent> for GameOverScreen {
         &mut self,
         data: StateData<PausableGameData>,
     ) -> Trans<PausableGameData<'a, 'b>, StateEvent> {
-        data.data.update(&data.world, false);
+        data.data.update(data.world, false);
         Trans::None
     }
 }
+
+/// Appends the run to `scores.txt` next to the game
+fn record_score(floors: u32, mode: DifficultyMode, seed: u64) {
+    let path = format!("{}/scores.txt", application_root_dir());
+    let line = format!("{} floors, {:?}, seed {}\n", floors, mode, seed);
+
+    let written = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(line.as_bytes()));
+
+    if let Err(err) = written {
+        error!("Could not record score in {}: {}", path, err);
+    }
+}
@@ -7,48 +7,329 @@
 
 
 
-use rand::{thread_rng, distributions::{Distribution, Poisson}, Rng};
+use rand::{distributions::{Distribution, Poisson}, Rng};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 use crate::game::Enemies;
+use crate::run_rng::RunRng;
 
-pub fn gen_enemy_list(floor: u32) -> Vec<Enemies> {
-    let mut rng = thread_rng();
-    let num_enemies: u64 = Poisson::new(floor as f64 + 0.5).sample(&mut rng).max((floor/2) as u64 + 1);
-
-    let mut out = Vec::new();
-    for _ in 0..num_enemies {
-        let enemy_type = {
-            if rng.gen_bool(0.35) {
-                Enemies::Stationary
-            } else if rng.gen_bool(0.35) {
-                Enemies::NoShoot
-            } else {
-                Enemies::Full
-            }
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum Interpolation {
+    Linear,
+    /// Holds each keyframe's value until the next one, for per band tables
+    Step,
+}
+
+impl Default for Interpolation {
+    fn default() -> Self {
+        Interpolation::Linear
+    }
+}
+
+/// A value by floor, given as `(floor, value)` keyframes in ascending floor order.
+/// Past the last keyframe the value keeps changing by `growth` per floor. `Step` curves keep
+/// stepping there too, in bands as wide as their last one.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Curve {
+    pub keys: Vec<(u32, f32)>,
+    #[serde(default)]
+    pub interpolation: Interpolation,
+    #[serde(default)]
+    pub growth: f32,
+}
+
+impl Curve {
+    pub fn new(keys: Vec<(u32, f32)>, interpolation: Interpolation, growth: f32) -> Self {
+        Self { keys, interpolation, growth }
+    }
+
+    pub fn at(&self, floor: u32) -> f32 {
+        let (first_floor, first) = match self.keys.first() {
+            Some(key) => *key,
+            None => return 0.,
         };
+        if floor <= first_floor {
+            return first;
+        }
 
-       out.push(enemy_type)
+        for pair in self.keys.windows(2) {
+            let (f0, v0) = pair[0];
+            let (f1, v1) = pair[1];
+            if floor < f1 {
+                return match self.interpolation {
+                    Interpolation::Step => v0,
+                    Interpolation::Linear => v0 + (v1 - v0) * (floor - f0) as f32 / (f1 - f0) as f32,
+                };
+            }
+        }
+
+        let (last_floor, last) = self.keys[self.keys.len() - 1];
+        let mut past = floor - last_floor;
+        if let Interpolation::Step = self.interpolation {
+            let band = match self.keys.len() {
+                1 => 1,
+                n => (last_floor - self.keys[n - 2].0).max(1),
+            };
+            past -= past % band;
+        }
+        last + self.growth * past as f32
     }
-    out
 }
 
+/// How the game scales with the floor, read from `resources/difficulty.ron`.
+/// Speeds are in units per second.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DifficultyCurve {
+    pub enemy_time: Curve,
+    pub enemy_speed: Curve,
+    pub powerup_cost: Curve,
+    pub enemy_bullet_speed: Curve,
+    /// Mean of the Poisson distribution the number of enemies in a room is drawn from
+    pub enemy_count_mean: Curve,
+    pub enemy_count_min: Curve,
+}
+
+impl DifficultyCurve {
+    pub fn gen_enemy_list(&self, floor: u32, rng: &mut RunRng) -> Vec<Enemies> {
+        let mean = self.enemy_count_mean.at(floor).max(0.01) as f64;
+        let min = self.enemy_count_min.at(floor).max(1.) as u64;
+        let num_enemies: u64 = Poisson::new(mean).sample(rng).max(min);
+
+        let mut out = Vec::new();
+        for _ in 0..num_enemies {
+            let enemy_type = {
+                if rng.gen_bool(0.35) {
+                    Enemies::Stationary
+                } else if rng.gen_bool(0.35) {
+                    Enemies::NoShoot
+                } else {
+                    Enemies::Full
+                }
+            };
+
+           out.push(enemy_type)
+        }
+        out
+    }
+
+    pub fn get_enemy_time(&self, floor: u32) -> u64 {
+        self.enemy_time.at(floor).max(1.) as u64
+    }
 
-pub fn get_enemy_time(floor: u32) -> u64 {
-    (floor as u64) * 5 + 25
+    pub fn get_enemy_speed(&self, floor: u32) -> f32 {
+        self.enemy_speed.at(floor)
+    }
+
+    pub fn get_powerup_cost(&self, floor: u32) -> u64 {
+        self.powerup_cost.at(floor).max(0.) as u64
+    }
+
+    pub fn get_enemy_bullet_speed(&self, floor: u32) -> f32 {
+        self.enemy_bullet_speed.at(floor)
+    }
+}
+
+impl DifficultyCurve {
+    /// Applies the enemy count and bullet speed multipliers of a difficulty mode
+    pub fn scaled(mut self, difficulty: &Difficulty) -> Self {
+        self.enemy_count_mean.scale(difficulty.enemy_count_scale);
+        self.enemy_count_min.scale(difficulty.enemy_count_scale);
+        self.enemy_bullet_speed.scale(difficulty.bullet_speed_scale);
+        self
+    }
+}
+
+impl Curve {
+    pub fn scale(&mut self, factor: f32) {
+        for key in self.keys.iter_mut() {
+            key.1 *= factor;
+        }
+        self.growth *= factor;
+    }
 }
 
-pub fn get_enemy_speed(floor: u32) -> f32 {
-    if floor > 5 {
-        2. + ( (floor-5) as f32 )*0.3
-    } else {
-        2.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DifficultyMode {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Default for DifficultyMode {
+    fn default() -> Self {
+        DifficultyMode::Normal
     }
 }
 
-pub fn get_powerup_cost(floor: u32) -> u64 {
-    5*(floor/5) as u64 + 10
+/// The mode picked on the title screen, fixed for the whole run. Everything but `mode` is read
+/// from `modes` in `resources/difficulty.ron`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Difficulty {
+    #[serde(skip)]
+    pub mode: DifficultyMode,
+    /// Name of the curve in `presets` the mode plays with
+    pub preset: String,
+    pub starting_time: u64,
+    /// Seconds lost when touching an enemy or getting shot
+    pub hit_penalty: u64,
+    /// Seconds gained when taking the teleport to the next floor
+    pub floor_bonus: u64,
+    /// Seconds spent on every dash
+    pub dash_cost: u64,
+    pub enemy_count_scale: f32,
+    pub bullet_speed_scale: f32,
 }
 
-pub fn get_enemy_bullet_speed(floor: u32) -> f32 {
-    (floor/3) as f32 + 3.
-}
\ No newline at end of file
+/// The contents of `resources/difficulty.ron`: the settings of each mode and any number of
+/// named curves for them to play with
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DifficultyPresets {
+    pub modes: HashMap<DifficultyMode, Difficulty>,
+    pub presets: HashMap<String, DifficultyCurve>,
+}
+
+impl DifficultyPresets {
+    pub fn get(&self, name: &str) -> &DifficultyCurve {
+        self.presets
+            .get(name)
+            .unwrap_or_else(|| panic!("No difficulty preset named {:?}", name))
+    }
+
+    pub fn difficulty(&self, mode: DifficultyMode) -> Difficulty {
+        let settings = self
+            .modes
+            .get(&mode)
+            .unwrap_or_else(|| panic!("No settings for {:?} in difficulty.ron", mode));
+        Difficulty {
+            mode,
+            ..settings.clone()
+        }
+    }
+}
+
+/// From the third floor on every other floor is procedurally generated
+pub fn use_generated_level(floor: u32) -> bool {
+    floor >= 3 && floor % 2 == 1
+}
+
+pub fn get_rooms_per_floor(floor: u32) -> usize {
+    (2 + floor as usize / 2).min(6)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use amethyst::config::Config;
+    use std::path::Path;
+
+    /// Frames per second the per frame formulas from before the curves assumed
+    const FPS: f32 = 60.;
+
+    fn normal_from_file() -> DifficultyCurve {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/difficulty.ron");
+        DifficultyPresets::load_no_fallback(&path).unwrap().get("normal").clone()
+    }
+
+    /// The old hard coded formulas, with speeds in units per frame
+    fn old_enemy_speed(floor: u32) -> f32 {
+        if floor > 5 {
+            2. + (floor - 5) as f32 * 0.3
+        } else {
+            2.
+        }
+    }
+
+    fn old_powerup_cost(floor: u32) -> u64 {
+        5 * (floor / 5) as u64 + 10
+    }
+
+    fn old_enemy_bullet_speed(floor: u32) -> f32 {
+        (floor / 3) as f32 + 3.
+    }
+
+    fn old_enemy_time(floor: u32) -> u64 {
+        floor as u64 * 5 + 25
+    }
+
+    fn assert_matches_old_formulas(curve: &DifficultyCurve) {
+        // Well past the last keys, where only the growth carries on
+        for floor in 0..100 {
+            assert!((curve.get_enemy_speed(floor) - old_enemy_speed(floor) * FPS).abs() < 1e-3);
+            assert_eq!(curve.get_powerup_cost(floor), old_powerup_cost(floor));
+            assert!(
+                (curve.get_enemy_bullet_speed(floor) - old_enemy_bullet_speed(floor) * FPS).abs()
+                    < 1e-3
+            );
+            assert_eq!(curve.get_enemy_time(floor), old_enemy_time(floor));
+        }
+    }
+
+    #[test]
+    fn enemy_speed_and_powerup_cost_keep_the_old_values() {
+        let curve = normal_from_file();
+        assert!((curve.get_enemy_speed(5) - 2.0 * FPS).abs() < 1e-3);
+        assert!((curve.get_enemy_speed(6) - 2.3 * FPS).abs() < 1e-3);
+        assert_eq!(curve.get_powerup_cost(0), 10);
+        assert_eq!(curve.get_powerup_cost(5), 15);
+        assert_eq!(curve.get_powerup_cost(10), 20);
+    }
+
+    #[test]
+    fn normal_preset_matches_old_formulas() {
+        assert_matches_old_formulas(&normal_from_file());
+    }
+
+    #[test]
+    fn every_mode_has_settings_and_a_curve() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/difficulty.ron");
+        let presets = DifficultyPresets::load_no_fallback(&path).unwrap();
+        for &mode in [DifficultyMode::Easy, DifficultyMode::Normal, DifficultyMode::Hard].iter() {
+            let difficulty = presets.difficulty(mode);
+            assert_eq!(difficulty.mode, mode);
+            assert!(presets.presets.contains_key(&difficulty.preset), "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn step_holds_each_key() {
+        let curve = Curve::new(vec![(0, 10.), (5, 15.), (10, 20.)], Interpolation::Step, 0.);
+        assert_eq!(curve.at(0), 10.);
+        assert_eq!(curve.at(4), 10.);
+        assert_eq!(curve.at(5), 15.);
+        assert_eq!(curve.at(9), 15.);
+        assert_eq!(curve.at(10), 20.);
+    }
+
+    #[test]
+    fn linear_goes_between_keys() {
+        let curve = Curve::new(vec![(2, 10.), (6, 30.)], Interpolation::Linear, 0.);
+        assert_eq!(curve.at(0), 10.);
+        assert_eq!(curve.at(2), 10.);
+        assert_eq!(curve.at(3), 15.);
+        assert_eq!(curve.at(5), 25.);
+        assert_eq!(curve.at(6), 30.);
+    }
+
+    #[test]
+    fn growth_continues_past_the_last_key() {
+        let curve = Curve::new(vec![(0, 1.), (4, 5.)], Interpolation::Linear, 0.5);
+        assert_eq!(curve.at(4), 5.);
+        assert_eq!(curve.at(5), 5.5);
+        assert_eq!(curve.at(10), 8.);
+
+        let step = Curve::new(vec![(0, 10.), (5, 15.)], Interpolation::Step, 2.);
+        assert_eq!(step.at(7), 15.);
+        assert_eq!(step.at(10), 25.);
+        assert_eq!(step.at(14), 25.);
+        assert_eq!(step.at(15), 35.);
+
+        let single = Curve::new(vec![(3, 1.)], Interpolation::Step, 2.);
+        assert_eq!(single.at(5), 5.);
+    }
+
+    #[test]
+    fn empty_curve_is_zero() {
+        assert_eq!(Curve::default().at(3), 0.);
+    }
+}
@@ -0,0 +1,182 @@
+use crate::game::ItemType;
+use crate::game_scale::{Curve, Interpolation};
+use crate::run_rng::RunRng;
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Rarity {
+    Common,
+    Uncommon,
+    Rare,
+}
+
+const RARITIES: [Rarity; 3] = [Rarity::Common, Rarity::Uncommon, Rarity::Rare];
+
+/// Where an item comes from, each has its own `DropTable`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DropSource {
+    Shop,
+    /// Next to the teleport once the exit room is cleared
+    FloorReward,
+    EnemyDrop,
+    BossDrop,
+}
+
+const DROP_SOURCES: [DropSource; 4] = [
+    DropSource::Shop,
+    DropSource::FloorReward,
+    DropSource::EnemyDrop,
+    DropSource::BossDrop,
+];
+
+/// What the player is told about an item, and how it looks lying on the floor
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ItemDef {
+    /// Short enough to fit in the label over the item
+    pub name: String,
+    /// Shown when the player walks up to the item
+    pub description: String,
+    /// Animation frames, the first one is also the sprite it spawns with. Never empty.
+    pub frames: Vec<usize>,
+    /// Price as a multiple of the floor's power up cost, 0 for free
+    pub cost: f32,
+    pub rarity: Rarity,
+}
+
+impl ItemDef {
+    pub fn sprite(&self) -> usize {
+        self.frames[0]
+    }
+
+    /// Seconds the item costs on a floor where power ups cost `powerup_cost`
+    pub fn price(&self, powerup_cost: u64) -> u64 {
+        (powerup_cost as f32 * self.cost).round().max(0.) as u64
+    }
+}
+
+fn always() -> Curve {
+    Curve::new(vec![(0, 1.)], Interpolation::Linear, 0.)
+}
+
+/// Picks what a `DropSource` gives: first whether anything drops, then a rarity by weight,
+/// then one of the listed items of that rarity
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DropTable {
+    /// Chance that anything drops at all, by floor
+    #[serde(default = "always")]
+    pub chance: Curve,
+    /// How likely each rarity is relative to the others, by floor
+    pub rarity_weights: HashMap<Rarity, Curve>,
+    pub items: Vec<ItemType>,
+}
+
+/// Every item and where it can turn up, read from `resources/items.ron`
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ItemRegistry {
+    pub items: HashMap<ItemType, ItemDef>,
+    pub drops: HashMap<DropSource, DropTable>,
+}
+
+impl ItemRegistry {
+    /// Panics on an item without frames, a missing drop table, or anything a drop table can
+    /// give that has no definition. Items only ever come out of the drop tables, so after this
+    /// neither `get` nor `roll` can fail.
+    pub fn validate(&self) {
+        for (kind, def) in self.items.iter() {
+            if def.frames.is_empty() {
+                panic!("{:?} ({}) has no frames in items.ron", kind, def.name);
+            }
+        }
+
+        for source in DROP_SOURCES.iter() {
+            let table = self
+                .drops
+                .get(source)
+                .unwrap_or_else(|| panic!("items.ron has no drop table for {:?}", source));
+            for kind in table.items.iter() {
+                if !self.items.contains_key(kind) {
+                    panic!("{:?} drops {:?}, which items.ron doesn't define", source, kind);
+                }
+            }
+        }
+    }
+
+    pub fn get(&self, kind: &ItemType) -> &ItemDef {
+        self.items
+            .get(kind)
+            .unwrap_or_else(|| panic!("No definition for item {:?}", kind))
+    }
+
+    /// Rolls the drop table of `source`, `None` when nothing drops
+    pub fn roll(&self, source: DropSource, floor: u32, rng: &mut RunRng) -> Option<ItemType> {
+        let table = &self.drops[&source];
+
+        let chance = table.chance.at(floor).max(0.).min(1.);
+        if !rng.gen_bool(chance as f64) {
+            return None;
+        }
+
+        let of_rarity = |rarity: Rarity| -> Vec<ItemType> {
+            table
+                .items
+                .iter()
+                .filter(|kind| self.get(kind).rarity == rarity)
+                .cloned()
+                .collect()
+        };
+
+        let weighted: Vec<(Rarity, f32)> = RARITIES
+            .iter()
+            .map(|&rarity| {
+                let weight = table.rarity_weights.get(&rarity).map_or(0., |w| w.at(floor));
+                (rarity, weight.max(0.))
+            })
+            .filter(|&(rarity, weight)| weight > 0. && !of_rarity(rarity).is_empty())
+            .collect();
+
+        let &(rarity, _) = weighted.choose_weighted(rng, |&(_, weight)| weight).ok()?;
+        of_rarity(rarity).choose(rng).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use amethyst::config::Config;
+    use std::path::Path;
+
+    fn from_file() -> ItemRegistry {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/items.ron");
+        ItemRegistry::load_no_fallback(&path).unwrap()
+    }
+
+    #[test]
+    fn items_file_is_complete() {
+        from_file().validate();
+    }
+
+    #[test]
+    #[should_panic(expected = "has no frames")]
+    fn item_without_frames_is_rejected() {
+        let mut registry = from_file();
+        for def in registry.items.values_mut() {
+            def.frames.clear();
+        }
+        registry.validate();
+    }
+
+    #[test]
+    fn rolls_only_give_listed_items() {
+        let registry = from_file();
+        let mut rng = RunRng::new(44);
+        for &source in DROP_SOURCES.iter() {
+            for floor in 0..20 {
+                if let Some(kind) = registry.roll(source, floor, &mut rng) {
+                    assert!(registry.drops[&source].items.contains(&kind));
+                }
+            }
+        }
+    }
+}
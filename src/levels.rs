@@ -0,0 +1,359 @@
+use crate::game::{GameMapTile, GAME_MAP_X, GAME_MAP_Y};
+use crate::maps::{self, game_map_tiles};
+use amethyst::config::Config;
+use itertools::iproduct;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A room as stored in `resources/levels/*.ron`.
+///
+/// `rows` are drawn the way the room looks in game: the first string is the top row and the
+/// last one is `y = 0`, each read left to right with the legend in `tile_from_char`. Rooms
+/// smaller than the map sit in its bottom left corner.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LevelFile {
+    pub name: String,
+    pub rows: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum LevelLoadError {
+    Parse(String),
+    TooManyRows(usize),
+    RowTooLong { row: usize, len: usize },
+    UnknownTile { row: usize, col: usize, tile: char },
+    Invalid(Vec<LevelError>),
+}
+
+/// ` ` nothing, `#` wall, `.` floor, `@` player start, `T` teleport
+pub fn tile_from_char(tile: char) -> Option<GameMapTile> {
+    match tile {
+        ' ' => Some(GameMapTile::Nothing),
+        '#' => Some(GameMapTile::Wall),
+        '.' => Some(GameMapTile::Floor),
+        '@' => Some(GameMapTile::PlayerStart),
+        'T' => Some(GameMapTile::Teleport),
+        _ => None,
+    }
+}
+
+impl LevelFile {
+    /// Turns the rows around into the `map[x][y]` the game uses
+    pub fn to_game_map(&self) -> Result<game_map_tiles, LevelLoadError> {
+        if self.rows.len() > GAME_MAP_Y {
+            return Err(LevelLoadError::TooManyRows(self.rows.len()));
+        }
+
+        let mut end = [[GameMapTile::Nothing; GAME_MAP_Y]; GAME_MAP_X];
+        for (row, line) in self.rows.iter().enumerate() {
+            let len = line.chars().count();
+            if len > GAME_MAP_X {
+                return Err(LevelLoadError::RowTooLong { row, len });
+            }
+
+            let y = self.rows.len() - 1 - row;
+            for (x, tile) in line.chars().enumerate() {
+                end[x][y] = tile_from_char(tile).ok_or(LevelLoadError::UnknownTile {
+                    row,
+                    col: x,
+                    tile,
+                })?;
+            }
+        }
+        Ok(end)
+    }
+}
+
+/// A rule a room breaks, found by `validate_level`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LevelError {
+    MissingPlayerStart,
+    MultiplePlayerStarts(usize),
+    MissingTeleport,
+    MultipleTeleports(usize),
+    /// No walkable path from the player start to the teleport.
+    TeleportUnreachable,
+    /// `CreatePowerUps` drops items either side of the teleport, and shops lay out their
+    /// stock there. These must be floor.
+    PowerUpSpotBlocked((usize, usize)),
+    /// `Enemies::spawn` needs at least one floor tile to place enemies on.
+    NoEnemySpawns,
+}
+
+fn is_walkable(tile: GameMapTile) -> bool {
+    match tile {
+        GameMapTile::Floor | GameMapTile::PlayerStart | GameMapTile::Teleport => true,
+        _ => false,
+    }
+}
+
+fn find_tiles(map: &game_map_tiles, kind: GameMapTile) -> Vec<(usize, usize)> {
+    iproduct!(0..GAME_MAP_X, 0..GAME_MAP_Y)
+        .filter(|&(x, y)| map[x][y] == kind)
+        .collect()
+}
+
+/// Flood fill over walkable tiles starting at `from`.
+pub fn reachable_tiles(
+    map: &game_map_tiles,
+    from: (usize, usize),
+) -> [[bool; GAME_MAP_Y]; GAME_MAP_X] {
+    let mut seen = [[false; GAME_MAP_Y]; GAME_MAP_X];
+    let mut stack = vec![from];
+    seen[from.0][from.1] = true;
+
+    while let Some((x, y)) = stack.pop() {
+        let mut neighbours = Vec::with_capacity(4);
+        if x > 0 {
+            neighbours.push((x - 1, y));
+        }
+        if x + 1 < GAME_MAP_X {
+            neighbours.push((x + 1, y));
+        }
+        if y > 0 {
+            neighbours.push((x, y - 1));
+        }
+        if y + 1 < GAME_MAP_Y {
+            neighbours.push((x, y + 1));
+        }
+
+        for (nx, ny) in neighbours {
+            if !seen[nx][ny] && is_walkable(map[nx][ny]) {
+                seen[nx][ny] = true;
+                stack.push((nx, ny));
+            }
+        }
+    }
+    seen
+}
+
+/// Checks a room against everything the game assumes about it when it is played.
+pub fn validate_level(map: &game_map_tiles) -> Result<(), Vec<LevelError>> {
+    let mut errors = Vec::new();
+
+    let starts = find_tiles(map, GameMapTile::PlayerStart);
+    let teleports = find_tiles(map, GameMapTile::Teleport);
+
+    match starts.len() {
+        0 => errors.push(LevelError::MissingPlayerStart),
+        1 => (),
+        n => errors.push(LevelError::MultiplePlayerStarts(n)),
+    }
+
+    match teleports.len() {
+        0 => errors.push(LevelError::MissingTeleport),
+        1 => (),
+        n => errors.push(LevelError::MultipleTeleports(n)),
+    }
+
+    if let (Some(&start), Some(&teleport)) = (starts.first(), teleports.first()) {
+        if !reachable_tiles(map, start)[teleport.0][teleport.1] {
+            errors.push(LevelError::TeleportUnreachable);
+        }
+    }
+
+    if let Some(&(x, y)) = teleports.first() {
+        let beside = [x.checked_sub(1), Some(x + 1)];
+        for spot in beside.iter() {
+            match spot {
+                Some(sx) if *sx < GAME_MAP_X && map[*sx][y] == GameMapTile::Floor => (),
+                Some(sx) => errors.push(LevelError::PowerUpSpotBlocked((*sx, y))),
+                None => errors.push(LevelError::PowerUpSpotBlocked((x, y))),
+            }
+        }
+    }
+
+    if find_tiles(map, GameMapTile::Floor).is_empty() {
+        errors.push(LevelError::NoEnemySpawns);
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+pub struct Level {
+    pub name: String,
+    pub map: game_map_tiles,
+}
+
+/// Every room discovered at startup; `start_new_level` picks from these.
+#[derive(Default)]
+pub struct LevelSet(pub Vec<Level>);
+
+impl LevelSet {
+    pub fn builtin() -> Self {
+        LevelSet(
+            maps::LEVELS
+                .iter()
+                .enumerate()
+                .map(|(i, base)| Level {
+                    name: format!("builtin {}", i + 1),
+                    map: maps::turn_into_game_map(*base),
+                })
+                .collect(),
+        )
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+pub fn load_level(path: &Path) -> Result<Level, LevelLoadError> {
+    let file = LevelFile::load_no_fallback(path)
+        .map_err(|err| LevelLoadError::Parse(format!("{:?}", err)))?;
+    let map = file.to_game_map()?;
+    validate_level(&map).map_err(LevelLoadError::Invalid)?;
+    Ok(Level {
+        name: file.name,
+        map,
+    })
+}
+
+/// Loads every `.ron` file in `dir`, sorted by file name. Broken files are skipped with an
+/// error in the log; if nothing could be loaded the rooms from `maps.rs` are used instead.
+///
+/// This reads the files directly rather than through the asset `Loader`: the `Loader` has no
+/// way to list a directory, and it loads in the background while `start_new_level` needs
+/// every room in `Game::on_start`.
+pub fn load_levels(dir: &Path) -> LevelSet {
+    let mut paths: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().map_or(false, |ext| ext == "ron"))
+            .collect(),
+        Err(err) => {
+            error!("Could not read level directory {:?}: {}", dir, err);
+            Vec::new()
+        }
+    };
+    paths.sort();
+
+    let mut levels = Vec::new();
+    for path in paths {
+        match load_level(&path) {
+            Ok(level) => {
+                info!("Loaded level {:?} from {:?}", level.name, path);
+                levels.push(level);
+            }
+            Err(err) => error!("Skipping level {:?}: {:?}", path, err),
+        }
+    }
+
+    if levels.is_empty() {
+        warn!("No levels found in {:?}, using the built in rooms", dir);
+        let builtin = LevelSet::builtin();
+        for level in builtin.0.iter() {
+            if let Err(errors) = validate_level(&level.map) {
+                error!("Built in level {:?} is broken: {:?}", level.name, errors);
+            }
+        }
+        builtin
+    } else {
+        LevelSet(levels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn room(rows: &[&str]) -> game_map_tiles {
+        LevelFile {
+            name: "test".to_string(),
+            rows: rows.iter().map(|row| row.to_string()).collect(),
+        }
+        .to_game_map()
+        .unwrap()
+    }
+
+    fn errors(rows: &[&str]) -> Vec<LevelError> {
+        validate_level(&room(rows)).unwrap_err()
+    }
+
+    #[test]
+    fn builtin_levels_are_valid() {
+        for (i, base) in maps::LEVELS.iter().enumerate() {
+            let map = maps::turn_into_game_map(*base);
+            assert_eq!(validate_level(&map), Ok(()), "builtin level {}", i + 1);
+        }
+    }
+
+    #[test]
+    fn level_files_are_valid() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/levels");
+        let mut found = 0;
+        for entry in fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().map_or(false, |ext| ext == "ron") {
+                found += 1;
+                if let Err(err) = load_level(&path) {
+                    panic!("{:?}: {:?}", path, err);
+                }
+            }
+        }
+        assert!(found > 0);
+    }
+
+    #[test]
+    fn first_row_is_the_top() {
+        let map = room(&["#@", "T."]);
+        assert_eq!(map[0][1], GameMapTile::Wall);
+        assert_eq!(map[1][1], GameMapTile::PlayerStart);
+        assert_eq!(map[0][0], GameMapTile::Teleport);
+        assert_eq!(map[1][0], GameMapTile::Floor);
+    }
+
+    #[test]
+    fn valid_room() {
+        let map = room(&["#######", "#.....#", "#.@.T.#", "#.....#", "#######"]);
+        assert_eq!(validate_level(&map), Ok(()));
+    }
+
+    #[test]
+    fn missing_player_start() {
+        let found = errors(&["#######", "#.....#", "#...T.#", "#######"]);
+        assert!(found.contains(&LevelError::MissingPlayerStart));
+    }
+
+    #[test]
+    fn multiple_player_starts() {
+        let found = errors(&["#######", "#.....#", "#@@.T.#", "#######"]);
+        assert!(found.contains(&LevelError::MultiplePlayerStarts(2)));
+    }
+
+    #[test]
+    fn missing_teleport() {
+        let found = errors(&["#######", "#.....#", "#.@...#", "#######"]);
+        assert!(found.contains(&LevelError::MissingTeleport));
+    }
+
+    #[test]
+    fn multiple_teleports() {
+        let found = errors(&["#######", "#@....#", "#.T.T.#", "#######"]);
+        assert!(found.contains(&LevelError::MultipleTeleports(2)));
+    }
+
+    #[test]
+    fn teleport_unreachable() {
+        let found = errors(&["########", "#..#...#", "#@.#.T.#", "#..#...#", "########"]);
+        assert_eq!(found, vec![LevelError::TeleportUnreachable]);
+    }
+
+    #[test]
+    fn power_up_spot_blocked() {
+        let found = errors(&["######", "#....#", "#.@.T#", "#....#", "######"]);
+        assert_eq!(found, vec![LevelError::PowerUpSpotBlocked((5, 2))]);
+    }
+
+    #[test]
+    fn no_enemy_spawns() {
+        let found = errors(&["####", "#@T#", "####"]);
+        assert!(found.contains(&LevelError::NoEnemySpawns));
+    }
+}
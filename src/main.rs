@@ -15,19 +15,46 @@ use amethyst::renderer::{
 use amethyst::ui::UiBundle;
 
 mod game;
-use crate::game::Game;
 
 mod pausable_game_data;
 use pausable_game_data::PausableGameDataBuilder;
 mod audio;
 mod pause_screen;
 mod game_over_screen;
+mod title_screen;
+use title_screen::TitleScreen;
 use audio::Music;
 #[rustfmt::skip]
 mod maps;
+mod levels;
+mod procgen;
+mod dungeon;
+mod run_rng;
+mod pathfinding;
+mod aabb;
+mod spatial_grid;
+mod collision;
+mod weapons;
+mod items;
+mod relics;
+mod actives;
 mod systems;
 mod game_scale;
 
+/// `--seed <u64>` replays a run
+fn seed_from_args() -> Option<u64> {
+    let args: Vec<String> = std::env::args().collect();
+    let seed = args.iter().position(|arg| arg == "--seed").map(|i| args.get(i + 1))?;
+
+    match seed.and_then(|seed| seed.parse().ok()) {
+        Some(seed) => Some(seed),
+        None => {
+            error!("--seed needs a number, starting with a random seed");
+            None
+        }
+    }
+}
+
 fn main() -> amethyst::Result<()> {
     amethyst::start_logger(Default::default());
     use amethyst::utils::application_root_dir;
@@ -60,28 +87,42 @@ fn main() -> amethyst::Result<()> {
         .with_base_bundle(AudioBundle::new(|music: &mut Music| music.music.next()))? //|music: &mut Music| music.music.next()))?
         .with_running_bundle(input_bundle)?
         .with_base_bundle(UiBundle::<String, String>::new())?
-        .with_running(systems::MovePlayerSystem, "move_player", &["input_system"])
-        .with_running(systems::GamePositionTransform, "gamepos_transform", &["move_player"])
+        .with_running(systems::StorePreviousPositions, "store_prev_positions", &[])
+        .with_running(systems::MovePlayerSystem, "move_player", &["input_system", "store_prev_positions"])
         .with_running(systems::MoveBadGuys,"move_bad_guys", &["move_player"])
-        .with_running(systems::PlayerBadGuyCollide, "collide_bad_guys", &["move_bad_guys"])
         .with_running(systems::DecrementTime, "decrement_time", &[])
         .with_running(systems::PlayerShoot, "player_shoot", &["move_player"])
         .with_running(systems::EnemyShoot, "enemy_shoot", &["move_player"])
         .with_running(systems::DecrementPlayerCoolDowns, "player_shoot_cooldowns", &["player_shoot"])
-        .with_running(systems::MoveBullets, "move_bullets", &["player_shoot"])
-        .with_running(systems::CheckBulletCollide, "bullet_collid", &["move_bullets"])
+        .with_running(systems::SteerHomingBullets, "steer_bullets", &["player_shoot", "enemy_shoot"])
+        .with_running(systems::MoveBullets, "move_bullets", &["player_shoot", "steer_bullets"])
+        .with_running(systems::ApplyKnockback, "apply_knockback", &["move_bad_guys"])
+        .with_running(systems::BuildSpatialGrid, "spatial_grid", &["apply_knockback", "move_bullets"])
+        .with_running(systems::DetectCollisions, "detect_collisions", &["spatial_grid"])
+        .with_running(systems::PlayerBadGuyCollide::default(), "collide_bad_guys", &["detect_collisions"])
+        .with_running(systems::SeparateEnemies::default(), "separate_enemies", &["detect_collisions"])
+        .with_running(systems::CheckBulletCollide::default(), "bullet_collid", &["detect_collisions"])
         .with_running(systems::RemoveOutOfTimeBadGuys, "kill_baddies", &["bullet_collid","decrement_time"])
-        .with_running(systems::CheckForNextRoom, "next_room", &["kill_baddies"])
+        .with_running(systems::UnlockDoors, "unlock_doors", &["kill_baddies"])
+        .with_running(systems::CheckForNextRoom, "next_room", &["kill_baddies", "unlock_doors"])
         .with_running(systems::UpdateTimer, "ui_timer", &["enemy_shoot", "decrement_time"])
         .with_running(systems::CreatePowerUps, "create_powerups", &["kill_baddies"])
+        .with_running(systems::StockShop, "stock_shop", &["next_room"])
         .with_running(systems::PickupItem, "pickup_items", &["move_player","player_shoot"])
+        .with_running(systems::BuyItem::default(), "buy_items", &["move_player"])
+        .with_running(systems::UseActiveItem, "use_active_item", &["move_player"])
         .with_running(systems::AnimateSprites, "animate_sprites", &["decrement_time"])
+        .with_frame(systems::GamePositionTransform, "gamepos_transform", &[])
+        .with_frame(systems::LabelItems, "label_items", &["gamepos_transform"])
+        .with_frame(systems::ShowItemTooltip, "item_tooltip", &["gamepos_transform"])
+        .with_frame(systems::ShowRelics, "show_relics", &[])
+        .with_frame(systems::ShowActiveItem, "show_active_item", &[])
 //        .with_running(systems::PaddleSystem, "paddle_system", &["input_system"])
 //        .with_running(systems::MoveBallSystem, "move_ball", &[])
 //        .with_running(systems::SoundFxSystem, "sound_fx", &["input_system"]);
     ;
 
-    let mut da_game = Application::new("./", Game, game_data)?;
+    let mut da_game = Application::new("./", TitleScreen::new(seed_from_args()), game_data)?;
 
     da_game.run();
 
@@ -0,0 +1,208 @@
+use crate::game::{GameMap, GameMapTile, GAME_MAP_X, GAME_MAP_Y};
+use amethyst::ecs::prelude::{Component, DenseVecStorage};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::time::Duration;
+
+pub type Tile = (usize, usize);
+
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+
+/// At most this many enemies look for a new path in a single fixed tick
+pub const MAX_REPATHS_PER_TICK: usize = 4;
+/// How long an enemy keeps following its path before looking for a new one
+pub const REPATH_INTERVAL_MS: u64 = 500;
+
+/// The route a chasing enemy is currently walking, waypoints in walking order
+#[derive(Component, Default, Debug)]
+#[storage(DenseVecStorage)]
+pub struct Pathing {
+    pub path: Vec<Tile>,
+    /// Tile the player was on when the path was found
+    pub target: Option<Tile>,
+    pub cooldown: Duration,
+}
+
+impl Pathing {
+    pub fn needs_repath(&self, player_tile: Tile) -> bool {
+        self.cooldown == Duration::from_secs(0)
+            && (self.path.is_empty() || self.target != Some(player_tile))
+    }
+
+    pub fn set_path(&mut self, path: Vec<Tile>, target: Tile) {
+        self.path = path;
+        self.target = Some(target);
+        self.cooldown = Duration::from_millis(REPATH_INTERVAL_MS);
+    }
+}
+
+pub fn is_passable(map: &GameMap, (x, y): Tile) -> bool {
+    map.map[x][y] != GameMapTile::Nothing && !map.blocks((x, y))
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+struct Node {
+    cost: u32,
+    tile: Tile,
+}
+
+// Flipped so the `BinaryHeap` pops the cheapest node first
+impl Ord for Node {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .cost
+            .cmp(&self.cost)
+            .then_with(|| self.tile.cmp(&other.tile))
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn heuristic((x1, y1): Tile, (x2, y2): Tile) -> u32 {
+    let dx = (x1 as i32 - x2 as i32).abs() as u32;
+    let dy = (y1 as i32 - y2 as i32).abs() as u32;
+    STRAIGHT_COST * dx.max(dy) + (DIAGONAL_COST - STRAIGHT_COST) * dx.min(dy)
+}
+
+/// Walkable neighbours with their step cost. Diagonal steps need both orthogonal tiles free
+/// so enemies don't clip the corners of walls.
+fn neighbours(map: &GameMap, (x, y): Tile) -> Vec<(Tile, u32)> {
+    let mut out = Vec::with_capacity(8);
+    for dx in -1i32..=1 {
+        for dy in -1i32..=1 {
+            if dx == 0 && dy == 0 {
+                continue;
+            }
+
+            let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+            if nx < 0 || ny < 0 || nx as usize >= GAME_MAP_X || ny as usize >= GAME_MAP_Y {
+                continue;
+            }
+            let next = (nx as usize, ny as usize);
+            if !is_passable(map, next) {
+                continue;
+            }
+
+            if dx != 0 && dy != 0 {
+                if !is_passable(map, (nx as usize, y)) || !is_passable(map, (x, ny as usize)) {
+                    continue;
+                }
+                out.push((next, DIAGONAL_COST));
+            } else {
+                out.push((next, STRAIGHT_COST));
+            }
+        }
+    }
+    out
+}
+
+/// A* over the tiles of `map`. The returned path leaves out `from` and ends on `to`, so a path
+/// from a tile to itself is just that tile.
+pub fn find_path(map: &GameMap, from: Tile, to: Tile) -> Option<Vec<Tile>> {
+    if !is_passable(map, to) {
+        return None;
+    }
+
+    let mut best = [[std::u32::MAX; GAME_MAP_Y]; GAME_MAP_X];
+    let mut came_from: [[Option<Tile>; GAME_MAP_Y]; GAME_MAP_X] = [[None; GAME_MAP_Y]; GAME_MAP_X];
+    let mut open = BinaryHeap::new();
+
+    best[from.0][from.1] = 0;
+    open.push(Node {
+        cost: heuristic(from, to),
+        tile: from,
+    });
+
+    while let Some(Node { tile, cost }) = open.pop() {
+        if tile == to {
+            let mut path = vec![to];
+            let mut current = to;
+            while let Some(prev) = came_from[current.0][current.1] {
+                if prev == from {
+                    break;
+                }
+                path.push(prev);
+                current = prev;
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        // Stale entry, a cheaper route to this tile was already expanded
+        if cost > best[tile.0][tile.1] + heuristic(tile, to) {
+            continue;
+        }
+
+        for (next, step) in neighbours(map, tile) {
+            let tentative = best[tile.0][tile.1] + step;
+            if tentative < best[next.0][next.1] {
+                best[next.0][next.1] = tentative;
+                came_from[next.0][next.1] = Some(tile);
+                open.push(Node {
+                    cost: tentative + heuristic(next, to),
+                    tile: next,
+                });
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An open map with walls on the given tiles
+    fn map_with_walls(walls: &[Tile]) -> GameMap {
+        let mut tiles = [[GameMapTile::Floor; GAME_MAP_Y]; GAME_MAP_X];
+        for &(x, y) in walls {
+            tiles[x][y] = GameMapTile::Wall;
+        }
+        GameMap::new(tiles, 0)
+    }
+
+    fn assert_walkable(map: &GameMap, from: Tile, path: &[Tile]) {
+        let mut current = from;
+        for &next in path {
+            assert!(is_passable(map, next), "{:?} is not walkable", next);
+            let dx = (next.0 as i32 - current.0 as i32).abs();
+            let dy = (next.1 as i32 - current.1 as i32).abs();
+            assert_eq!(dx.max(dy), 1, "{:?} to {:?} is not one step", current, next);
+            current = next;
+        }
+    }
+
+    #[test]
+    fn goes_around_a_wall() {
+        // A wall across x = 3 with a gap only in the top row
+        let walls: Vec<Tile> = (0..GAME_MAP_Y - 1).map(|y| (3, y)).collect();
+        let map = map_with_walls(&walls);
+
+        let path = find_path(&map, (1, 0), (5, 0)).unwrap();
+        assert_eq!(path.last(), Some(&(5, 0)));
+        assert!(!path.contains(&(1, 0)));
+        assert!(path.contains(&(3, GAME_MAP_Y - 1)));
+        assert_walkable(&map, (1, 0), &path);
+    }
+
+    #[test]
+    fn unreachable_goal_has_no_path() {
+        let walls: Vec<Tile> = (0..GAME_MAP_Y).map(|y| (3, y)).collect();
+        let map = map_with_walls(&walls);
+
+        assert_eq!(find_path(&map, (1, 0), (5, 0)), None);
+        assert_eq!(find_path(&map, (1, 0), (3, 4)), None);
+    }
+
+    #[test]
+    fn start_on_the_goal() {
+        let map = map_with_walls(&[]);
+        assert_eq!(find_path(&map, (4, 4), (4, 4)), Some(vec![(4, 4)]));
+    }
+}
@@ -1,19 +1,54 @@
+use amethyst::core::timing::Time;
 use amethyst::core::{ArcThreadPool, SystemBundle};
 use amethyst::ecs::{Dispatcher, DispatcherBuilder, System, World};
 use amethyst::{DataInit, Error, Result};
+use std::time::Duration;
+
+/// Rate the running systems are ticked at, whatever the frame rate
+pub const FIXED_TICKS_PER_SECOND: u32 = 120;
+/// After a long stall the simulation falls behind instead of trying to catch up all at once
+const MAX_TICKS_PER_FRAME: u32 = 8;
+
+/// How far the current frame is between the last two fixed ticks, from 0 to 1
+#[derive(Default, Debug)]
+pub struct FixedStepAlpha(pub f32);
 
 pub struct PausableGameData<'a, 'b> {
     core_dispatcher: Dispatcher<'a, 'b>,
     running_dispatcher: Dispatcher<'a, 'b>,
+    frame_dispatcher: Dispatcher<'a, 'b>,
+    accumulator: Duration,
 
     pub game_over: bool,
     pub floors_cleared: u32,
 }
 
 impl<'a, 'b> PausableGameData<'a, 'b> {
-    pub fn update(&mut self, world: &World, running: bool) {
+    /// Runs the running systems zero or more times on a fixed step, then the per frame ones.
+    /// While the running systems tick `Time::delta_time` is the fixed step. The world is
+    /// maintained after every tick, so the next one doesn't see entities deleted by the last.
+    pub fn update(&mut self, world: &mut World, running: bool) {
         if running {
-            self.running_dispatcher.dispatch(&world.res);
+            let step = Duration::from_nanos(1_000_000_000 / FIXED_TICKS_PER_SECOND as u64);
+            let frame_delta = world.read_resource::<Time>().delta_time();
+
+            self.accumulator += frame_delta;
+            if self.accumulator > step * MAX_TICKS_PER_FRAME {
+                self.accumulator = step * MAX_TICKS_PER_FRAME;
+            }
+
+            while self.accumulator >= step {
+                world.write_resource::<Time>().set_delta_time(step);
+                self.running_dispatcher.dispatch(&world.res);
+                world.maintain();
+                self.accumulator -= step;
+            }
+            world.write_resource::<Time>().set_delta_time(frame_delta);
+
+            let alpha = self.accumulator.subsec_nanos() as f32 / step.subsec_nanos() as f32;
+            world.write_resource::<FixedStepAlpha>().0 = alpha;
+
+            self.frame_dispatcher.dispatch(&world.res);
         }
         self.core_dispatcher.dispatch(&world.res);
     }
@@ -22,6 +57,7 @@ impl<'a, 'b> PausableGameData<'a, 'b> {
 pub struct PausableGameDataBuilder<'a, 'b> {
     pub core: DispatcherBuilder<'a, 'b>,
     pub running: DispatcherBuilder<'a, 'b>,
+    pub frame: DispatcherBuilder<'a, 'b>,
 }
 
 impl<'a, 'b> Default for PausableGameDataBuilder<'a, 'b> {
@@ -35,6 +71,7 @@ impl<'a, 'b> PausableGameDataBuilder<'a, 'b> {
         PausableGameDataBuilder {
             core: DispatcherBuilder::new(),
             running: DispatcherBuilder::new(),
+            frame: DispatcherBuilder::new(),
         }
     }
 
@@ -56,6 +93,16 @@ impl<'a, 'b> PausableGameDataBuilder<'a, 'b> {
         self
     }
 
+    /// Adds a system that runs once per rendered frame while the game is running,
+    /// after the fixed ticks
+    pub fn with_frame<S>(mut self, system: S, name: &str, dependencies: &[&str]) -> Self
+    where
+        for<'c> S: System<'c> + Send + 'a,
+    {
+        self.frame.add(system, name, dependencies);
+        self
+    }
+
     pub fn with_running_bundle<B>(mut self, bundle: B) -> Result<Self>
     where
         B: SystemBundle<'a, 'b>,
@@ -73,12 +120,17 @@ impl<'a, 'b> DataInit<PausableGameData<'a, 'b>> for PausableGameDataBuilder<'a,
 
         let mut core_dispatcher = self.core.with_pool(pool.clone()).build();
         let mut running_dispatcher = self.running.with_pool(pool.clone()).build();
+        let mut frame_dispatcher = self.frame.with_pool(pool.clone()).build();
         core_dispatcher.setup(&mut world.res);
         running_dispatcher.setup(&mut world.res);
+        frame_dispatcher.setup(&mut world.res);
+        world.add_resource(FixedStepAlpha::default());
 
         PausableGameData {
             core_dispatcher,
             running_dispatcher,
+            frame_dispatcher,
+            accumulator: Duration::from_secs(0),
             floors_cleared: 0,
             game_over: false,
         }
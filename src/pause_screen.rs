@@ -72,7 +72,7 @@ impl<'a, 'b> State<PausableGameData<'a, 'b>, StateEvent> for Paused {
         &mut self,
         data: StateData<PausableGameData>,
     ) -> Trans<PausableGameData<'a, 'b>, StateEvent> {
-        data.data.update(&data.world, false);
+        data.data.update(data.world, false);
         Trans::None
     }
 }
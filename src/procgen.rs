@@ -0,0 +1,164 @@
+use crate::game::{GameMapTile, GAME_MAP_X, GAME_MAP_Y};
+use crate::levels::validate_level;
+use crate::maps::{self, game_map_tiles};
+use itertools::iproduct;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+const ROOM_ATTEMPTS: usize = 40;
+const MAX_ROOMS: usize = 7;
+const MIN_ROOM_SIZE: usize = 4;
+const MAX_ROOM_SIZE: usize = 9;
+/// Layouts tried for one seed before giving up and using a builtin room
+const MAX_LEVEL_ATTEMPTS: usize = 100;
+
+#[derive(Clone, Copy, Debug)]
+struct Rect {
+    x: usize,
+    y: usize,
+    w: usize,
+    h: usize,
+}
+
+impl Rect {
+    fn center(&self) -> (usize, usize) {
+        (self.x + self.w / 2, self.y + self.h / 2)
+    }
+
+    /// Rooms need a tile between them so their walls don't merge.
+    fn intersects(&self, other: &Rect) -> bool {
+        self.x <= other.x + other.w
+            && other.x <= self.x + self.w
+            && self.y <= other.y + other.h
+            && other.y <= self.y + self.h
+    }
+}
+
+/// Rooms-and-corridors generator. The same seed always gives the same room, and every
+/// room it returns passes `validate_level`. A seed that gives no valid layout in
+/// `MAX_LEVEL_ATTEMPTS` tries gets the first builtin room instead of hanging the floor change.
+pub fn generate_level(seed: u64) -> game_map_tiles {
+    let mut rng = StdRng::seed_from_u64(seed);
+    for _ in 0..MAX_LEVEL_ATTEMPTS {
+        let map = try_generate(&mut rng);
+        if validate_level(&map).is_ok() {
+            return map;
+        }
+    }
+
+    warn!("No valid level from seed {}, using a builtin one", seed);
+    maps::turn_into_game_map(maps::LEVELS[0])
+}
+
+fn try_generate(rng: &mut StdRng) -> game_map_tiles {
+    let mut map = [[GameMapTile::Nothing; GAME_MAP_Y]; GAME_MAP_X];
+    let mut rooms: Vec<Rect> = Vec::new();
+
+    for _ in 0..ROOM_ATTEMPTS {
+        if rooms.len() == MAX_ROOMS {
+            break;
+        }
+
+        let w = rng.gen_range(MIN_ROOM_SIZE, MAX_ROOM_SIZE + 1);
+        let h = rng.gen_range(MIN_ROOM_SIZE, MAX_ROOM_SIZE + 1);
+        // Keep a border of one tile free for the outer walls
+        let room = Rect {
+            x: rng.gen_range(1, GAME_MAP_X - w - 1),
+            y: rng.gen_range(1, GAME_MAP_Y - h - 1),
+            w,
+            h,
+        };
+
+        if rooms.iter().any(|other| other.intersects(&room)) {
+            continue;
+        }
+
+        for (x, y) in iproduct!(room.x..room.x + room.w, room.y..room.y + room.h) {
+            map[x][y] = GameMapTile::Floor;
+        }
+
+        if let Some(prev) = rooms.last() {
+            carve_corridor(&mut map, prev.center(), room.center(), rng.gen_bool(0.5));
+        }
+
+        rooms.push(room);
+    }
+
+    if rooms.len() < 2 {
+        return map;
+    }
+
+    let (sx, sy) = rooms[0].center();
+    let (ex, ey) = rooms[rooms.len() - 1].center();
+    map[sx][sy] = GameMapTile::PlayerStart;
+    map[ex][ey] = GameMapTile::Teleport;
+
+    add_walls(&mut map);
+    map
+}
+
+fn carve_corridor(
+    map: &mut game_map_tiles,
+    (x1, y1): (usize, usize),
+    (x2, y2): (usize, usize),
+    x_first: bool,
+) {
+    let corner = if x_first { (x2, y1) } else { (x1, y2) };
+
+    for x in x1.min(x2)..=x1.max(x2) {
+        map[x][corner.1] = GameMapTile::Floor;
+    }
+    for y in y1.min(y2)..=y1.max(y2) {
+        map[corner.0][y] = GameMapTile::Floor;
+    }
+}
+
+/// Surrounds every walkable tile with walls.
+fn add_walls(map: &mut game_map_tiles) {
+    let walkable = *map;
+    for (x, y) in iproduct!(0..GAME_MAP_X, 0..GAME_MAP_Y) {
+        if walkable[x][y] != GameMapTile::Nothing {
+            continue;
+        }
+
+        let near_floor = iproduct!(x.saturating_sub(1)..=x + 1, y.saturating_sub(1)..=y + 1)
+            .filter(|&(nx, ny)| nx < GAME_MAP_X && ny < GAME_MAP_Y)
+            .any(|(nx, ny)| walkable[nx][ny] != GameMapTile::Nothing);
+
+        if near_floor {
+            map[x][y] = GameMapTile::Wall;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::levels::reachable_tiles;
+
+    #[test]
+    fn same_seed_same_level() {
+        for seed in 0..20 {
+            assert_eq!(&generate_level(seed)[..], &generate_level(seed)[..], "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn generated_levels_are_valid_and_connected() {
+        let builtin = maps::turn_into_game_map(maps::LEVELS[0]);
+        for seed in 0..200 {
+            let map = generate_level(seed);
+            assert!(map[..] != builtin[..], "seed {} fell back to a builtin level", seed);
+            assert_eq!(validate_level(&map), Ok(()), "seed {}", seed);
+
+            let (sx, sy) = iproduct!(0..GAME_MAP_X, 0..GAME_MAP_Y)
+                .find(|&(x, y)| map[x][y] == GameMapTile::PlayerStart)
+                .unwrap();
+            let reachable = reachable_tiles(&map, (sx, sy));
+            for (x, y) in iproduct!(0..GAME_MAP_X, 0..GAME_MAP_Y) {
+                if map[x][y] == GameMapTile::Floor {
+                    assert!(reachable[x][y], "seed {}: floor at {:?} is cut off", seed, (x, y));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use crate::game::{Item, ItemType};
+use amethyst::ecs::prelude::{Component, VecStorage};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RelicKind {
+    /// Every enemy that dies gives back a little time
+    KillRefund,
+    /// Getting hit costs half as much, but power ups cost double
+    ThickSkin,
+    /// Player bullets that break on a wall burst into smaller ones
+    SplitShot,
+}
+
+/// A number the game asks the held relics about before using it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stat {
+    /// Seconds given back when an enemy dies
+    KillRefund,
+    /// Seconds lost when the player is hit
+    HitPenalty,
+    /// Seconds a power up costs, whether it is bought or picked up
+    PowerUpCost,
+    /// Bullets a player bullet splits into when it hits a wall
+    WallSplits,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Modifier {
+    Add(f32),
+    Multiply(f32),
+}
+
+impl RelicKind {
+    pub fn modifiers(self) -> &'static [(Stat, Modifier)] {
+        use self::Modifier::*;
+        match self {
+            RelicKind::KillRefund => &[(Stat::KillRefund, Add(2.))],
+            RelicKind::ThickSkin => &[
+                (Stat::HitPenalty, Multiply(0.5)),
+                (Stat::PowerUpCost, Multiply(2.)),
+            ],
+            RelicKind::SplitShot => &[(Stat::WallSplits, Add(2.))],
+        }
+    }
+}
+
+/// The relics the player has picked up this run
+#[derive(Component, Debug, Default)]
+#[storage(VecStorage)]
+pub struct Relics {
+    pub held: Vec<RelicKind>,
+}
+
+impl Relics {
+    /// `base` with every held relic's modifiers for `stat`, the additions before the
+    /// multiplications
+    pub fn modify(&self, stat: Stat, base: f32) -> f32 {
+        let (mut add, mut multiply) = (0., 1.);
+        for kind in self.held.iter() {
+            for &(modified, modifier) in kind.modifiers().iter() {
+                match modifier {
+                    _ if modified != stat => (),
+                    Modifier::Add(amount) => add += amount,
+                    Modifier::Multiply(factor) => multiply *= factor,
+                }
+            }
+        }
+        (base + add) * multiply
+    }
+
+    pub fn seconds(&self, stat: Stat, base: u64) -> Duration {
+        let seconds = self.modify(stat, base as f32).max(0.);
+        Duration::from_millis((seconds * 1000.).round() as u64)
+    }
+
+    /// What `item` really costs the player
+    pub fn price(&self, item: &Item) -> u64 {
+        match item.kind {
+            ItemType::PowerUp(_) => {
+                self.modify(Stat::PowerUpCost, item.cost as f32).round().max(0.) as u64
+            }
+            _ => item.cost,
+        }
+    }
+
+    pub fn apply_item(&mut self, item: &Item) {
+        match item.kind {
+            ItemType::Relic(kind) => {
+                self.held.push(kind);
+            }
+            _ => (),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::PowerUps;
+    use crate::weapons::WeaponKind;
+
+    fn holding(held: &[RelicKind]) -> Relics {
+        Relics { held: held.to_vec() }
+    }
+
+    #[test]
+    fn adds_before_multiplying() {
+        let relics = holding(&[RelicKind::KillRefund, RelicKind::KillRefund]);
+        assert_eq!(relics.modify(Stat::KillRefund, 1.), 5.);
+        assert_eq!(relics.modify(Stat::HitPenalty, 10.), 10.);
+        assert_eq!(holding(&[RelicKind::ThickSkin]).modify(Stat::HitPenalty, 15.), 7.5);
+    }
+
+    #[test]
+    fn seconds_keep_fractions() {
+        let relics = holding(&[RelicKind::ThickSkin]);
+        assert_eq!(relics.seconds(Stat::HitPenalty, 15), Duration::from_millis(7500));
+    }
+
+    #[test]
+    fn thick_skin_only_doubles_power_ups() {
+        let relics = holding(&[RelicKind::ThickSkin]);
+        let power_up = Item::new((0, 0), ItemType::PowerUp(PowerUps::Speed), 10);
+        let weapon = Item::new((0, 0), ItemType::Weapon(WeaponKind::Beam), 20);
+        let relic = Item::new((0, 0), ItemType::Relic(RelicKind::SplitShot), 25);
+        assert_eq!(relics.price(&power_up), 20);
+        assert_eq!(relics.price(&weapon), 20);
+        assert_eq!(relics.price(&relic), 25);
+        assert_eq!(Relics::default().price(&power_up), 10);
+    }
+}
@@ -0,0 +1,35 @@
+use rand::{rngs::StdRng, Error, RngCore, SeedableRng};
+
+/// The single source of randomness for a run. Floors, enemies, item rolls and animation
+/// offsets all draw from it, so replaying a seed replays the run.
+pub struct RunRng {
+    pub seed: u64,
+    rng: StdRng,
+}
+
+impl RunRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl RngCore for RunRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
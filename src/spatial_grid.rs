@@ -0,0 +1,59 @@
+use crate::aabb::Aabb;
+use crate::game::{CollisionDetectionFlag, GamePosition, TILE_SIZE};
+use amethyst::ecs::prelude::Entity;
+use std::collections::HashMap;
+
+/// Side of a grid cell, two tiles so most entities only touch a few cells
+pub const CELL_SIZE: f32 = 2. * TILE_SIZE as f32;
+
+type Cell = (i32, i32);
+
+/// Uniform grid of every colliding entity, rebuilt each tick so collision systems
+/// only compare things that are close to each other
+#[derive(Default, Debug)]
+pub struct SpatialGrid {
+    cells: HashMap<Cell, Vec<Entity>>,
+}
+
+/// Cells covered by the entity's box
+fn cell_range(pos: &GamePosition, coll: &CollisionDetectionFlag) -> (Cell, Cell) {
+    let cell = |v: f32| (v / CELL_SIZE).floor() as i32;
+    let aabb = Aabb::of(pos, coll);
+    let (min, max) = (aabb.min(), aabb.max());
+    ((cell(min[0]), cell(min[1])), (cell(max[0]), cell(max[1])))
+}
+
+impl SpatialGrid {
+    /// Empties every cell but keeps their allocations for the next tick
+    pub fn clear(&mut self) {
+        for cell in self.cells.values_mut() {
+            cell.clear();
+        }
+    }
+
+    pub fn insert(&mut self, entity: Entity, pos: &GamePosition, coll: &CollisionDetectionFlag) {
+        let ((x0, y0), (x1, y1)) = cell_range(pos, coll);
+        for x in x0..=x1 {
+            for y in y0..=y1 {
+                self.cells.entry((x, y)).or_insert_with(Vec::new).push(entity);
+            }
+        }
+    }
+
+    /// Entities sharing a cell with the given box, each once. They still need a
+    /// `check_collision` to know if they actually overlap.
+    pub fn query(&self, pos: &GamePosition, coll: &CollisionDetectionFlag) -> Vec<Entity> {
+        let ((x0, y0), (x1, y1)) = cell_range(pos, coll);
+        let mut out = Vec::new();
+        for x in x0..=x1 {
+            for y in y0..=y1 {
+                if let Some(cell) = self.cells.get(&(x, y)) {
+                    out.extend_from_slice(cell);
+                }
+            }
+        }
+        out.sort();
+        out.dedup();
+        out
+    }
+}
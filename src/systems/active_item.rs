@@ -0,0 +1,67 @@
+use crate::aabb::Aabb;
+use crate::actives::{ActiveItem, ActiveKind, BOMB_RADIUS};
+use crate::game::{CollisionDetectionFlag, GamePosition, PlayerEntity};
+use crate::systems::shoot::EnemyBullet;
+use amethyst::core::timing::Time;
+use amethyst::ecs::{Entities, Join, Read, ReadStorage, System, WriteStorage};
+use amethyst::input::InputHandler;
+
+/// Runs down the effect of the last used active item and uses the held one on the use key
+pub struct UseActiveItem;
+
+impl<'a> System<'a> for UseActiveItem {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, ActiveItem>,
+        Read<'a, PlayerEntity>,
+        Read<'a, InputHandler<String, String>>,
+        Read<'a, Time>,
+        ReadStorage<'a, GamePosition>,
+        ReadStorage<'a, CollisionDetectionFlag>,
+        ReadStorage<'a, EnemyBullet>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            mut active_store,
+            player_ent,
+            input,
+            time,
+            game_poses,
+            coll_flags,
+            enemy_bullets,
+        ) = data;
+
+        let player_ent = player_ent.0.unwrap();
+        let active = active_store.get_mut(player_ent).unwrap();
+        active.tick(time.delta_time());
+
+        if !input.action_is_down("use_item").unwrap_or(false) {
+            return;
+        }
+
+        match active.use_item() {
+            Some(ActiveKind::Bomb) => {
+                let player_center = Aabb::of(
+                    game_poses.get(player_ent).unwrap(),
+                    coll_flags.get(player_ent).unwrap(),
+                )
+                .center;
+
+                for (ent, _, pos, coll) in
+                    (&entities, &enemy_bullets, &game_poses, &coll_flags).join()
+                {
+                    let center = Aabb::of(pos, coll).center;
+                    let dist = (center[0] - player_center[0]).hypot(center[1] - player_center[1]);
+                    if dist <= BOMB_RADIUS {
+                        entities.delete(ent).unwrap();
+                    }
+                }
+            }
+            // The others last a while and are checked for where they matter
+            Some(kind) => warn!("Used: {:?}", kind),
+            None => (),
+        }
+    }
+}
@@ -16,7 +16,7 @@ impl<'a> System<'a> for AnimateSprites {
 
         for (anim, sprite) in (&mut anim_store, &mut sprite_store).join() {
             if anim.cooldown == Duration::from_secs(0) {
-                sprite.sprite_number = *anim.sprites.next().unwrap();
+                sprite.sprite_number = anim.sprites.next().unwrap();
                 anim.cooldown = anim.time_per_frame;
             }
         }
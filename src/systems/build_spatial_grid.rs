@@ -0,0 +1,24 @@
+use crate::game::{CollisionDetectionFlag, GamePosition};
+use crate::spatial_grid::SpatialGrid;
+use amethyst::ecs::{Entities, Join, ReadStorage, System, Write};
+
+/// Refills the `SpatialGrid` once everything has moved this tick
+pub struct BuildSpatialGrid;
+
+impl<'a> System<'a> for BuildSpatialGrid {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, GamePosition>,
+        ReadStorage<'a, CollisionDetectionFlag>,
+        Write<'a, SpatialGrid>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, game_poses, coll_flags, mut grid) = data;
+
+        grid.clear();
+        for (ent, pos, coll) in (&entities, &game_poses, &coll_flags).join() {
+            grid.insert(ent, pos, coll);
+        }
+    }
+}
@@ -1,12 +1,22 @@
+use crate::actives::{ActiveItem, ActiveKind};
+use crate::dungeon::{DungeonFloor, DungeonRoom, RoomKind};
 use crate::game::{
-    start_new_level, FloorsVisited, GameMap, GamePosition, Item, ItemType, LoadedSpriteSheet,
-    NumEnemiesLeft, Player, PlayerEntity, PowerUps, RoomFlag, Shooter, TimeLeft,
+    enter_room, start_new_level, Animation, DoorFlag, Dropped, FloorsVisited, ForSale, GameMap,
+    GameMapTile, GamePosition, Item, ItemType, LoadedSpriteSheet, NumEnemiesLeft, Player,
+    PlayerEntity, RoomFlag, Shooter, TimeLeft, WallFlag, DOOR_OPEN_SPRITE,
 };
+use crate::game_scale::{Difficulty, DifficultyCurve};
+use crate::levels::LevelSet;
+use crate::run_rng::RunRng;
+use crate::items::{DropSource, ItemRegistry};
+use crate::relics::Relics;
+use crate::weapons::Weapon;
 use amethyst::core::Transform;
 use amethyst::ecs::{
-    Builder, Entities, Join, LazyUpdate, Read, ReadExpect, ReadStorage, System, WriteExpect,
-    WriteStorage,
+    Builder, Entities, Join, LazyBuilder, LazyUpdate, Read, ReadExpect, ReadStorage, System,
+    WriteExpect, WriteStorage,
 };
+use amethyst::input::InputHandler;
 use amethyst::renderer::{SpriteRender, Transparent};
 use std::time::Duration;
 
@@ -25,6 +35,12 @@ impl<'a> System<'a> for CheckForNextRoom {
         ReadStorage<'a, RoomFlag>,
         WriteExpect<'a, FloorsVisited>,
         WriteStorage<'a, TimeLeft>,
+        ReadExpect<'a, LevelSet>,
+        WriteExpect<'a, DungeonFloor>,
+        WriteExpect<'a, RunRng>,
+        ReadExpect<'a, DifficultyCurve>,
+        ReadExpect<'a, Difficulty>,
+        WriteStorage<'a, ActiveItem>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -39,21 +55,59 @@ impl<'a> System<'a> for CheckForNextRoom {
             lazy,
             room_flag,
             mut floors,
-            mut time_left_store
+            mut time_left_store,
+            levels,
+            mut dungeon,
+            mut rng,
+            difficulty,
+            difficulty_mode,
+            mut active_store,
         ) = data;
 
         let player = player_ent.0.unwrap();
         let player_pos = game_poses.get(player_ent.0.unwrap()).unwrap().clone();
         let time_left = time_left_store.get_mut(player_ent.0.unwrap()).unwrap();
+        let active = active_store.get_mut(player).unwrap();
+
+        let (x, y) = player_pos.to_tile();
+
+        if let GameMapTile::Door(side) = gamemap.map[x][y] {
+            let next = dungeon.current_room().neighbours[side as usize];
+            if let (false, Some(next)) = (gamemap.doors_locked, next) {
+                for (ent, _) in (&entities, &room_flag).join() {
+                    entities.delete(ent).unwrap();
+                }
+                charge_for_room(dungeon.current_room_mut(), active);
 
-        if num_enemies.0 == 0 && player_pos.to_tile() == gamemap.ending_spot {
+                enter_room(
+                    &mut gamemap,
+                    &mut dungeon,
+                    next,
+                    Some(side.opposite()),
+                    game_poses,
+                    player,
+                    &sprite_sheet,
+                    &mut num_enemies,
+                    entities,
+                    &lazy,
+                    &floors,
+                    &difficulty,
+                    &mut rng,
+                );
+            }
+        } else if num_enemies.0 == 0
+            && dungeon.current_room().is_exit
+            && (x, y) == gamemap.ending_spot
+        {
             for (ent, _) in (&entities, &room_flag).join() {
 
                 entities.delete(ent).unwrap();
             }
-            time_left.add(Duration::from_secs(10));
+            charge_for_room(dungeon.current_room_mut(), active);
+            time_left.add(Duration::from_secs(difficulty_mode.floor_bonus));
             start_new_level(
                 &mut gamemap,
+                &mut dungeon,
                 game_poses,
                 player,
                 &sprite_sheet,
@@ -61,6 +115,9 @@ impl<'a> System<'a> for CheckForNextRoom {
                 entities,
                 &lazy,
                 &floors,
+                &levels,
+                &difficulty,
+                &mut rng,
             );
 
             floors.0 += 1;
@@ -69,6 +126,51 @@ impl<'a> System<'a> for CheckForNextRoom {
     }
 }
 
+/// Puts a charge into the active item for leaving a room the player cleared, once per room
+fn charge_for_room(room: &mut DungeonRoom, active: &mut ActiveItem) {
+    if room.cleared && !room.charged {
+        room.charged = true;
+        active.recharge();
+    }
+}
+
+/// Opens the doors of the current room once its last enemy is gone
+pub struct UnlockDoors;
+
+impl<'a> System<'a> for UnlockDoors {
+    type SystemData = (
+        ReadExpect<'a, NumEnemiesLeft>,
+        WriteExpect<'a, GameMap>,
+        WriteExpect<'a, DungeonFloor>,
+        Entities<'a>,
+        ReadStorage<'a, DoorFlag>,
+        WriteStorage<'a, SpriteRender>,
+        WriteStorage<'a, WallFlag>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            num_enemies,
+            mut gamemap,
+            mut dungeon,
+            entities,
+            door_flag,
+            mut sprites,
+            mut walls,
+        ) = data;
+
+        if gamemap.doors_locked && num_enemies.0 == 0 {
+            gamemap.doors_locked = false;
+            dungeon.current_room_mut().cleared = true;
+
+            for (ent, _, sprite) in (&entities, &door_flag, &mut sprites).join() {
+                sprite.sprite_number = DOOR_OPEN_SPRITE;
+                walls.remove(ent);
+            }
+        }
+    }
+}
+
 pub struct CreatePowerUps;
 
 impl<'a> System<'a> for CreatePowerUps {
@@ -78,36 +180,65 @@ impl<'a> System<'a> for CreatePowerUps {
         Read<'a, LazyUpdate>,
         ReadExpect<'a, LoadedSpriteSheet>,
         WriteExpect<'a, GameMap>,
+        WriteExpect<'a, RunRng>,
+        ReadExpect<'a, DifficultyCurve>,
+        ReadExpect<'a, FloorsVisited>,
+        ReadExpect<'a, ItemRegistry>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (num_enemies, entities, lazy, sprite_sheet, mut game_map) = data;
+        let (
+            num_enemies,
+            entities,
+            lazy,
+            sprite_sheet,
+            mut game_map,
+            mut rng,
+            difficulty,
+            floors,
+            registry,
+        ) = data;
 
         if num_enemies.0 == 0 && !game_map.powerups_spawned {
             let (x, y) = game_map.ending_spot;
             game_map.powerups_spawned = true;
 
-            spawn_powerup((x + 1, y), &lazy, &entities, &sprite_sheet,10);
-            spawn_powerup((x - 1, y), &lazy, &entities, &sprite_sheet,10);
+            let cost = difficulty.get_powerup_cost(floors.0);
+
+            for &spot in [(x + 1, y), (x - 1, y)].iter() {
+                spawn_drop(
+                    spot,
+                    &lazy,
+                    &entities,
+                    &sprite_sheet,
+                    &registry,
+                    DropSource::FloorReward,
+                    floors.0,
+                    cost,
+                    &mut rng,
+                );
+            }
         }
     }
 }
 
-pub fn spawn_powerup(
+/// Rolls the drop table of `source` and puts whatever comes up at `location`, priced for
+/// a floor where power ups cost `powerup_cost`
+pub fn spawn_drop(
     location: (usize, usize),
     lazy: &LazyUpdate,
     entities: &Entities,
     sprite_sheet: &LoadedSpriteSheet,
-    cost: u64,
+    registry: &ItemRegistry,
+    source: DropSource,
+    floor: u32,
+    powerup_cost: u64,
+    rng: &mut RunRng,
 ) {
-    spawn_item(
-        location,
-        lazy,
-        entities,
-        sprite_sheet,
-        ItemType::PowerUp(PowerUps::get_random()),
-        cost,
-    );
+    if let Some(item_type) = registry.roll(source, floor, rng) {
+        let cost = registry.get(&item_type).price(powerup_cost);
+        spawn_item(location, lazy, entities, sprite_sheet, registry, item_type, cost, rng);
+    }
 }
 
 pub fn spawn_item(
@@ -115,15 +246,31 @@ pub fn spawn_item(
     lazy: &LazyUpdate,
     entities: &Entities,
     sprite_sheet: &LoadedSpriteSheet,
+    registry: &ItemRegistry,
     item_type: ItemType,
-    cost: u64
+    cost: u64,
+    rng: &mut RunRng,
 ) {
+    item_builder(location, lazy, entities, sprite_sheet, registry, item_type, cost, rng).build();
+}
+
+fn item_builder<'a>(
+    location: (usize, usize),
+    lazy: &'a LazyUpdate,
+    entities: &Entities,
+    sprite_sheet: &LoadedSpriteSheet,
+    registry: &ItemRegistry,
+    item_type: ItemType,
+    cost: u64,
+    rng: &mut RunRng,
+) -> LazyBuilder<'a> {
+    let def = registry.get(&item_type);
     let sprite_other_floor = SpriteRender {
         sprite_sheet: sprite_sheet.0.clone().unwrap(),
-        sprite_number: item_type.to_sprite_sheet_num(), // paddle is the first sprite in the sprite_sheet
+        sprite_number: def.sprite(),
     };
 
-    let anim = item_type.get_anim();
+    let anim = Animation::new(200, &def.frames, rng);
 
     let mut transform = Transform::default();
 
@@ -135,6 +282,139 @@ pub fn spawn_item(
         .with(Transparent)
         .with(RoomFlag)
         .with(anim)
+}
+
+/// Items a shop puts out, side by side around its counter
+const SHOP_STOCK_SIZE: usize = 3;
+
+/// Puts out what the shop in the current room has left, stocking it on the first visit, and
+/// any active items the player dropped in the room
+pub struct StockShop;
+
+impl<'a> System<'a> for StockShop {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, LazyUpdate>,
+        ReadExpect<'a, LoadedSpriteSheet>,
+        WriteExpect<'a, GameMap>,
+        WriteExpect<'a, DungeonFloor>,
+        WriteExpect<'a, RunRng>,
+        ReadExpect<'a, DifficultyCurve>,
+        ReadExpect<'a, FloorsVisited>,
+        ReadExpect<'a, ItemRegistry>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            lazy,
+            sprite_sheet,
+            mut game_map,
+            mut dungeon,
+            mut rng,
+            difficulty,
+            floors,
+            registry,
+        ) = data;
+
+        if game_map.items_spawned {
+            return;
+        }
+        game_map.items_spawned = true;
+
+        let room = dungeon.current_room_mut();
+        for item in room.dropped.iter() {
+            item_builder(
+                item.location,
+                &lazy,
+                &entities,
+                &sprite_sheet,
+                &registry,
+                item.kind,
+                item.cost,
+                &mut rng,
+            )
+            .with(Dropped)
+            .build();
+        }
+
+        let (x, y) = match room.kind {
+            RoomKind::Shop { counter } => counter,
+            RoomKind::Combat => return,
+        };
+
+        if room.stock.is_none() {
+            let cost = difficulty.get_powerup_cost(floors.0);
+            let left = x - SHOP_STOCK_SIZE / 2;
+            let stock = (0..SHOP_STOCK_SIZE)
+                .map(|i| (left + i, y))
+                .filter(|&spot| !game_map.blocks(spot))
+                .filter_map(|spot| {
+                    let kind = registry.roll(DropSource::Shop, floors.0, &mut rng)?;
+                    Some(Item::new(spot, kind, registry.get(&kind).price(cost)))
+                })
+                .collect();
+            room.stock = Some(stock);
+        }
+
+        for item in room.stock.as_ref().unwrap().iter() {
+            item_builder(
+                item.location,
+                &lazy,
+                &entities,
+                &sprite_sheet,
+                &registry,
+                item.kind,
+                item.cost,
+                &mut rng,
+            )
+            .with(ForSale)
+            .build();
+        }
+    }
+}
+
+/// Hands `item` to the player, paying its cost in time. Returns the active item it pushed
+/// out of the player's slot, if any.
+fn give_item(
+    item: &Item,
+    player: &mut Player,
+    shooter: &mut Shooter,
+    time_left: &mut TimeLeft,
+    weapon: &mut Weapon,
+    relics: &mut Relics,
+    active: &mut ActiveItem,
+) -> Option<ActiveKind> {
+    let paid = Item {
+        cost: relics.price(item),
+        ..*item
+    };
+    player.apply_item(item);
+    shooter.apply_item(item);
+    time_left.apply_item(&paid);
+    weapon.apply_item(item);
+    relics.apply_item(item);
+    active.apply_item(item)
+}
+
+/// Leaves a swapped out active item where the player stands, already paid for. It goes into the
+/// room's `dropped` too, so it is still there when the player comes back.
+fn drop_active(
+    kind: ActiveKind,
+    location: (usize, usize),
+    dropped: &mut Vec<Item>,
+    lazy: &LazyUpdate,
+    entities: &Entities,
+    sprite_sheet: &LoadedSpriteSheet,
+    registry: &ItemRegistry,
+    rng: &mut RunRng,
+) {
+    warn!("Dropped: {:?}", kind);
+    let item = Item::new(location, ItemType::Active(kind), 0);
+    dropped.push(item);
+
+    item_builder(location, lazy, entities, sprite_sheet, registry, item.kind, item.cost, rng)
+        .with(Dropped)
         .build();
 }
 
@@ -149,6 +429,16 @@ impl<'a> System<'a> for PickupItem {
         Read<'a, PlayerEntity>,
         Entities<'a>,
         ReadStorage<'a, GamePosition>,
+        WriteStorage<'a, Weapon>,
+        ReadStorage<'a, ForSale>,
+        WriteStorage<'a, Relics>,
+        WriteStorage<'a, ActiveItem>,
+        WriteStorage<'a, Dropped>,
+        Read<'a, LazyUpdate>,
+        ReadExpect<'a, LoadedSpriteSheet>,
+        ReadExpect<'a, ItemRegistry>,
+        WriteExpect<'a, RunRng>,
+        WriteExpect<'a, DungeonFloor>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -160,20 +450,164 @@ impl<'a> System<'a> for PickupItem {
             player_ent,
             entities,
             game_poses,
+            mut weapon_store,
+            for_sale,
+            mut relics_store,
+            mut active_store,
+            mut dropped,
+            lazy,
+            sprite_sheet,
+            registry,
+            mut rng,
+            mut dungeon,
         ) = data;
 
-        let player_pos = game_poses.get(player_ent.0.unwrap()).unwrap().clone();
+        let player_tile = game_poses.get(player_ent.0.unwrap()).unwrap().to_tile();
+        let room = dungeon.current_room_mut();
         let player = player_flag.get_mut(player_ent.0.unwrap()).unwrap();
         let shooter = shooter_flag.get_mut(player_ent.0.unwrap()).unwrap();
         let time_left = time_left_store.get_mut(player_ent.0.unwrap()).unwrap();
+        let weapon = weapon_store.get_mut(player_ent.0.unwrap()).unwrap();
+        let relics = relics_store.get_mut(player_ent.0.unwrap()).unwrap();
+        let active = active_store.get_mut(player_ent.0.unwrap()).unwrap();
+
+        let stepped_off: Vec<_> = (&entities, &item_store, &dropped)
+            .join()
+            .filter(|(_, item, _)| item.location != player_tile)
+            .map(|(ent, ..)| ent)
+            .collect();
+        for ent in stepped_off {
+            dropped.remove(ent);
+        }
 
-        for (ent, item) in (&entities, &item_store).join() {
-            if item.location == player_pos.to_tile() {
+        for (ent, item, _, _) in (&entities, &item_store, !&for_sale, !&dropped).join() {
+            if item.location == player_tile {
                 warn!("Picked up: {:?}", item);
-                player.apply_item(item);
-                shooter.apply_item(item);
-                time_left.apply_item(item);
+                let swapped = give_item(item, player, shooter, time_left, weapon, relics, active);
                 entities.delete(ent).unwrap();
+                let picked = room.dropped.iter().position(|left| {
+                    left.location == item.location && left.kind == item.kind
+                });
+                if let Some(idx) = picked {
+                    room.dropped.remove(idx);
+                }
+
+                if let Some(kind) = swapped {
+                    drop_active(
+                        kind,
+                        player_tile,
+                        &mut room.dropped,
+                        &lazy,
+                        &entities,
+                        &sprite_sheet,
+                        &registry,
+                        &mut rng,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Buys the shop item the player stands on when the buy key is pressed. Nothing is sold
+/// that would use up the last of the player's time.
+#[derive(Default)]
+pub struct BuyItem {
+    /// Whether the buy key was down last tick, holding it only buys once
+    buy_was_down: bool,
+}
+
+impl<'a> System<'a> for BuyItem {
+    type SystemData = (
+        ReadStorage<'a, Item>,
+        ReadStorage<'a, ForSale>,
+        WriteStorage<'a, Player>,
+        WriteStorage<'a, Shooter>,
+        WriteStorage<'a, TimeLeft>,
+        WriteStorage<'a, Weapon>,
+        Read<'a, PlayerEntity>,
+        Entities<'a>,
+        ReadStorage<'a, GamePosition>,
+        Read<'a, InputHandler<String, String>>,
+        WriteExpect<'a, DungeonFloor>,
+        WriteStorage<'a, Relics>,
+        WriteStorage<'a, ActiveItem>,
+        Read<'a, LazyUpdate>,
+        ReadExpect<'a, LoadedSpriteSheet>,
+        ReadExpect<'a, ItemRegistry>,
+        WriteExpect<'a, RunRng>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            item_store,
+            for_sale,
+            mut player_flag,
+            mut shooter_flag,
+            mut time_left_store,
+            mut weapon_store,
+            player_ent,
+            entities,
+            game_poses,
+            input,
+            mut dungeon,
+            mut relics_store,
+            mut active_store,
+            lazy,
+            sprite_sheet,
+            registry,
+            mut rng,
+        ) = data;
+
+        let buy_down = input.action_is_down("buy").unwrap_or(false);
+        let pressed = buy_down && !self.buy_was_down;
+        self.buy_was_down = buy_down;
+        if !pressed {
+            return;
+        }
+
+        let player_ent = player_ent.0.unwrap();
+        let player_tile = game_poses.get(player_ent).unwrap().to_tile();
+        let player = player_flag.get_mut(player_ent).unwrap();
+        let shooter = shooter_flag.get_mut(player_ent).unwrap();
+        let time_left = time_left_store.get_mut(player_ent).unwrap();
+        let weapon = weapon_store.get_mut(player_ent).unwrap();
+        let relics = relics_store.get_mut(player_ent).unwrap();
+        let active = active_store.get_mut(player_ent).unwrap();
+
+        let room = dungeon.current_room_mut();
+        let stock = match room.stock.as_mut() {
+            Some(stock) => stock,
+            None => return,
+        };
+
+        for (ent, item, _) in (&entities, &item_store, &for_sale).join() {
+            if item.location != player_tile
+                || !stock.iter().any(|left| left.location == item.location)
+            {
+                continue;
+            }
+            if time_left.remainaing <= Duration::from_secs(relics.price(item)) {
+                warn!("Can't afford: {:?}", item);
+                continue;
+            }
+
+            warn!("Bought: {:?}", item);
+            let swapped = give_item(item, player, shooter, time_left, weapon, relics, active);
+            stock.retain(|left| left.location != item.location);
+            entities.delete(ent).unwrap();
+
+            if let Some(kind) = swapped {
+                drop_active(
+                    kind,
+                    player_tile,
+                    &mut room.dropped,
+                    &lazy,
+                    &entities,
+                    &sprite_sheet,
+                    &registry,
+                    &mut rng,
+                );
             }
         }
     }
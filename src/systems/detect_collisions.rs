@@ -0,0 +1,46 @@
+use crate::collision::{CollisionEvent, CollisionLayer};
+use crate::game::{CollisionDetectionFlag, GamePosition};
+use crate::spatial_grid::SpatialGrid;
+use crate::systems::player_bad_guy_collide::check_collision;
+use amethyst::ecs::{Entities, Join, Read, ReadStorage, System, Write};
+use amethyst::shrev::EventChannel;
+
+/// The one place overlapping entities are found, everything else reads the `CollisionEvent`s
+pub struct DetectCollisions;
+
+impl<'a> System<'a> for DetectCollisions {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, GamePosition>,
+        ReadStorage<'a, CollisionDetectionFlag>,
+        ReadStorage<'a, CollisionLayer>,
+        Read<'a, SpatialGrid>,
+        Write<'a, EventChannel<CollisionEvent>>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, game_poses, coll_flags, layers, grid, mut events) = data;
+
+        for (a, a_pos, a_coll, a_layer) in (&entities, &game_poses, &coll_flags, &layers).join() {
+            if a_layer.mask == 0 {
+                continue;
+            }
+
+            for b in grid.query(a_pos, a_coll) {
+                if a == b {
+                    continue;
+                }
+
+                let (b_pos, b_coll, b_layer) =
+                    match (game_poses.get(b), coll_flags.get(b), layers.get(b)) {
+                        (Some(pos), Some(coll), Some(layer)) => (pos, coll, layer),
+                        _ => continue,
+                    };
+
+                if a_layer.wants(b_layer) && check_collision(*a_pos, *a_coll, *b_pos, *b_coll) {
+                    events.single_write(CollisionEvent { a, b });
+                }
+            }
+        }
+    }
+}
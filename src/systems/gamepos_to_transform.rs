@@ -1,32 +1,71 @@
 pub struct GamePositionTransform;
 
-use crate::game::{GamePosition, Player, ARENA_HEIGHT, ARENA_WIDTH};
+use crate::game::{GamePosition, Player, PreviousGamePosition, ARENA_HEIGHT, ARENA_WIDTH, TILE_SIZE};
+use crate::pausable_game_data::FixedStepAlpha;
 use amethyst::core::Transform;
-use amethyst::ecs::{Join, Read, ReadStorage, System, WriteStorage};
+use amethyst::ecs::{Entities, Join, Read, ReadStorage, System, WriteStorage};
+
+/// Blends between the last two fixed ticks. Anything that jumped more than a tile,
+/// like the player going through a door, is drawn where it is now.
+fn interpolate(game_pos: &GamePosition, prev: Option<&PreviousGamePosition>, alpha: f32) -> [f32; 2] {
+    match prev {
+        Some(prev)
+            if (game_pos.0[0] - prev.0[0]).abs() + (game_pos.0[1] - prev.0[1]).abs()
+                < TILE_SIZE as f32 =>
+        {
+            [
+                prev.0[0] + (game_pos.0[0] - prev.0[0]) * alpha,
+                prev.0[1] + (game_pos.0[1] - prev.0[1]) * alpha,
+            ]
+        }
+        _ => game_pos.0,
+    }
+}
 
 impl<'a> System<'a> for GamePositionTransform {
     type SystemData = (
         ReadStorage<'a, GamePosition>,
+        ReadStorage<'a, PreviousGamePosition>,
         WriteStorage<'a, Transform>,
         ReadStorage<'a, Player>,
+        Read<'a, FixedStepAlpha>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (game_poses, mut transforms, player_flag) = data;
+        let (game_poses, prev_poses, mut transforms, player_flag, alpha) = data;
 
         let mut camera = [ARENA_WIDTH / 2., ARENA_HEIGHT / 2.];
 
-        for (_, game_pos) in (&player_flag, &game_poses).join() {
-            camera[0] = ARENA_WIDTH / 2. - game_pos.0[0];
-            camera[1] = ARENA_HEIGHT / 2. - game_pos.0[1];
+        for (_, game_pos, prev) in (&player_flag, &game_poses, prev_poses.maybe()).join() {
+            let pos = interpolate(game_pos, prev, alpha.0);
+            camera[0] = ARENA_WIDTH / 2. - pos[0];
+            camera[1] = ARENA_HEIGHT / 2. - pos[1];
         }
 
-        for (game_pos, transform) in (&game_poses, &mut transforms).join() {
-            let x = game_pos.0[0];
-            let y = game_pos.0[1];
+        for (game_pos, prev, transform) in (&game_poses, prev_poses.maybe(), &mut transforms).join() {
+            let pos = interpolate(game_pos, prev, alpha.0);
+
+            transform.set_x(pos[0] + camera[0]);
+            transform.set_y(pos[1] + camera[1]);
+        }
+    }
+}
+
+/// Remembers every position before a fixed tick moves anything
+pub struct StorePreviousPositions;
+
+impl<'a> System<'a> for StorePreviousPositions {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, GamePosition>,
+        WriteStorage<'a, PreviousGamePosition>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, game_poses, mut prev_poses) = data;
 
-            transform.set_x(x + camera[0]);
-            transform.set_y(y + camera[1]);
+        for (ent, game_pos) in (&entities, &game_poses).join() {
+            prev_poses.insert(ent, PreviousGamePosition(game_pos.0)).unwrap();
         }
     }
 }
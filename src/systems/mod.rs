@@ -1,18 +1,27 @@
 mod move_player;
-pub use self::move_player::{MoveBadGuys, MovePlayerSystem};
+pub use self::move_player::{ApplyKnockback, MoveBadGuys, MovePlayerSystem};
 mod gamepos_to_transform;
-pub use self::gamepos_to_transform::GamePositionTransform;
+pub use self::gamepos_to_transform::{GamePositionTransform, StorePreviousPositions};
+mod build_spatial_grid;
+pub use self::build_spatial_grid::BuildSpatialGrid;
+mod detect_collisions;
+pub use self::detect_collisions::DetectCollisions;
 mod player_bad_guy_collide;
-pub use self::player_bad_guy_collide::PlayerBadGuyCollide;
+pub use self::player_bad_guy_collide::{PlayerBadGuyCollide, SeparateEnemies};
 mod time;
 pub use self::time::{DecrementTime, RemoveOutOfTimeBadGuys};
 mod shoot;
 pub use self::shoot::{
     CheckBulletCollide, DecrementPlayerCoolDowns, EnemyShoot, MoveBullets, PlayerShoot,
+    SteerHomingBullets,
 };
 mod check_next_room;
-pub use self::check_next_room::{CheckForNextRoom, CreatePowerUps, PickupItem};
+pub use self::check_next_room::{
+    BuyItem, CheckForNextRoom, CreatePowerUps, PickupItem, StockShop, UnlockDoors,
+};
 mod ui_stuff;
-pub use self::ui_stuff::UpdateTimer;
+pub use self::ui_stuff::{LabelItems, ShowActiveItem, ShowItemTooltip, ShowRelics, UpdateTimer};
+mod active_item;
+pub use self::active_item::UseActiveItem;
 mod animation;
 pub use self::animation::AnimateSprites;
\ No newline at end of file
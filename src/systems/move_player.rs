@@ -1,9 +1,22 @@
 use crate::game::{
-    CollisionDetectionFlag, EnemyFlag, GameMap, GameMapTile, GamePosition, Player, PlayerEntity,
-    ShootDirection, TILE_SIZE,
+    CollisionDetectionFlag, EnemyFlag, GameMap, GamePosition, Knockback, Player, PlayerEntity,
+    ShootDirection, TimeLeft,
 };
+use crate::aabb::Aabb;
+use crate::actives::{ActiveItem, ActiveKind};
+use crate::game_scale::Difficulty;
+use crate::pathfinding::{find_path, Pathing, MAX_REPATHS_PER_TICK};
+use amethyst::core::timing::Time;
 use amethyst::ecs::{Join, Read, ReadExpect, ReadStorage, System, WriteStorage};
 use amethyst::input::InputHandler;
+use std::time::Duration;
+
+/// Distance a dash covers
+const DASH_DISTANCE: f32 = 180.;
+const DASH_MS: u64 = 150;
+const DASH_COOLDOWN_MS: u64 = 800;
+/// Invincibility left over after the dash ends
+const DASH_GRACE_MS: u64 = 100;
 
 pub struct MovePlayerSystem;
 
@@ -14,25 +27,79 @@ impl<'a> System<'a> for MovePlayerSystem {
         Read<'a, InputHandler<String, String>>,
         ReadExpect<'a, GameMap>,
         ReadStorage<'a, CollisionDetectionFlag>,
+        Read<'a, Time>,
+        WriteStorage<'a, TimeLeft>,
+        ReadExpect<'a, Difficulty>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut game_poses, mut player_flag, input, game_map, coll_flags) = data;
-        for (player, game_pos, coll_flag) in (&mut player_flag, &mut game_poses, &coll_flags).join()
+        let (
+            mut game_poses,
+            mut player_flag,
+            input,
+            game_map,
+            coll_flags,
+            time,
+            mut time_left_store,
+            difficulty,
+        ) = data;
+        let dt = time.delta_seconds();
+        if dt == 0. {
+            return;
+        }
+
+        for (player, game_pos, coll_flag, time_left) in (
+            &mut player_flag,
+            &mut game_poses,
+            &coll_flags,
+            &mut time_left_store,
+        )
+            .join()
         {
             let x_movement = input.axis_value("player_leftright");
             let y_movement = input.axis_value("player_updown");
+
+            if player.dash_cooldown > time.delta_time() {
+                player.dash_cooldown -= time.delta_time();
+            } else {
+                player.dash_cooldown = Duration::from_secs(0);
+            }
+
+            if player.dash_time == Duration::from_secs(0)
+                && player.dash_cooldown == Duration::from_secs(0)
+                && input.action_is_down("dash").unwrap_or(false)
+            {
+                start_dash(
+                    player,
+                    [
+                        x_movement.unwrap_or(0.) as f32,
+                        y_movement.unwrap_or(0.) as f32,
+                    ],
+                    time_left,
+                    &difficulty,
+                );
+            }
+
+            if player.dash_time > Duration::from_secs(0) {
+                let step = dash_step(player, time.delta_time());
+                for idx in 0..2 {
+                    let moved = try_move(game_pos, idx, step[idx], &game_map, coll_flag);
+                    player.speed[idx] = moved / dt;
+                }
+                continue;
+            }
+
             if let Some(mv_amount) = y_movement {
-                let scaled_amount = player.speed_multi * mv_amount as f32;
+                let scaled_amount = player.speed_multi * mv_amount as f32 * dt;
                 let player_y = game_pos.0[1];
 
                 let moved = try_move(game_pos, 1, scaled_amount, &game_map, coll_flag);
-                player.speed[1] = moved;
+                player.speed[1] = moved / dt;
             }
             if let Some(mv_amount) = x_movement {
-                let scaled_amount = player.speed_multi * mv_amount as f32;
+                let scaled_amount = player.speed_multi * mv_amount as f32 * dt;
                 let moved = try_move(game_pos, 0, scaled_amount, &game_map, coll_flag);
-                player.speed[0] = moved;
+                player.speed[0] = moved / dt;
             }
             //println!("{:?}", input.action_is_down("hi"));
 
@@ -53,6 +120,45 @@ impl<'a> System<'a> for MovePlayerSystem {
     }
 }
 
+/// Sends the player off along `direction`, if they are moving at all
+fn start_dash(
+    player: &mut Player,
+    direction: [f32; 2],
+    time_left: &mut TimeLeft,
+    difficulty: &Difficulty,
+) {
+    let r = direction[0].hypot(direction[1]);
+    if r == 0. {
+        return;
+    }
+
+    let dash_speed = DASH_DISTANCE * 1000. / DASH_MS as f32;
+    player.dash_velocity = [
+        dash_speed * direction[0] / r,
+        dash_speed * direction[1] / r,
+    ];
+    player.dash_time = Duration::from_millis(DASH_MS);
+    player.dash_cooldown = Duration::from_millis(DASH_COOLDOWN_MS);
+
+    let invincible = Duration::from_millis(DASH_MS + DASH_GRACE_MS);
+    if player.invincible_time < invincible {
+        player.invincible_time = invincible;
+    }
+
+    time_left.subtract(Duration::from_secs(difficulty.dash_cost));
+}
+
+/// How far the dash goes this tick, counting down its time. The last tick only goes as far as
+/// the time left, so the whole dash covers `DASH_DISTANCE` whatever the tick length.
+fn dash_step(player: &mut Player, delta: Duration) -> [f32; 2] {
+    let step = player.dash_time.min(delta);
+    player.dash_time -= step;
+
+    let secs = step.as_secs() as f32 + step.subsec_nanos() as f32 / 1_000_000_000.;
+    [player.dash_velocity[0] * secs, player.dash_velocity[1] * secs]
+}
+
+/// Moves along one axis as far as the walls allow and returns how far that was
 fn try_move(
     game_pos: &mut GamePosition,
     idx: usize,
@@ -60,29 +166,9 @@ fn try_move(
     game_map: &GameMap,
     coll_flag: &CollisionDetectionFlag,
 ) -> f32 {
-    let mut new_game_pos = GamePosition(game_pos.0);
-    let mut new_game_pos_coll = GamePosition(game_pos.0);
-    new_game_pos.0[idx] += amount;
-    new_game_pos_coll.0[idx] += amount;
-    new_game_pos_coll.0[idx] += coll_flag.0[idx];
-
-    let (x, y) = new_game_pos.to_tile();
-    let (x_c, y_c) = new_game_pos_coll.to_tile();
-
-    if game_map.map[x][y] == GameMapTile::Wall || game_map.map[x_c][y_c] == GameMapTile::Wall {
-        let diff = if game_map.map[x][y] == GameMapTile::Wall {
-            let wall_pos = GamePosition::from_tile((x, y));
-            wall_pos.0[idx] - game_pos.0[idx] + TILE_SIZE as f32
-        } else {
-            let wall_pos = GamePosition::from_tile((x_c, y_c));
-            wall_pos.0[idx] - game_pos.0[idx] - coll_flag.0[idx]
-        };
-        game_pos.0[idx] += diff;
-        diff
-    } else {
-        game_pos.0[idx] += amount;
-        amount
-    }
+    let moved = Aabb::of(game_pos, coll_flag).sweep_axis(idx, amount, game_map);
+    game_pos.0[idx] += moved;
+    moved
 }
 
 pub struct MoveBadGuys;
@@ -94,24 +180,163 @@ impl<'a> System<'a> for MoveBadGuys {
         ReadExpect<'a, GameMap>,
         ReadStorage<'a, CollisionDetectionFlag>,
         Read<'a, PlayerEntity>,
+        WriteStorage<'a, Pathing>,
+        Read<'a, Time>,
+        ReadStorage<'a, ActiveItem>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut game_poses, enemy_flag, game_map, coll_flags, player_ent) = data;
+        let (
+            mut game_poses,
+            enemy_flag,
+            game_map,
+            coll_flags,
+            player_ent,
+            mut pathings,
+            time,
+            active_store,
+        ) = data;
+
+        let active = active_store.get(player_ent.0.unwrap()).unwrap();
+        if active.is_active(ActiveKind::TimeStop) {
+            return;
+        }
 
         let player_pos = game_poses.get(player_ent.0.unwrap()).unwrap().clone();
+        let player_coll = coll_flags.get(player_ent.0.unwrap()).unwrap().clone();
+        let player_tile = player_pos.center_tile(&player_coll);
+
+        let mut repaths_left = MAX_REPATHS_PER_TICK;
 
-        for (enm_flag, pos, coll_flag) in (&enemy_flag, &mut game_poses, &coll_flags).join() {
+        for (enm_flag, pos, coll_flag, pathing) in
+            (&enemy_flag, &mut game_poses, &coll_flags, (&mut pathings).maybe()).join()
+        {
             if enm_flag.moves {
-                let pos_diff = [player_pos.0[0] - pos.0[0], player_pos.0[1] - pos.0[1]];
+                let step = enm_flag.speed * time.delta_seconds();
+                let enemy_tile = pos.center_tile(coll_flag);
+
+                let mut target = player_pos.0;
+
+                if let Some(pathing) = pathing {
+                    if pathing.cooldown > time.delta_time() {
+                        pathing.cooldown -= time.delta_time();
+                    } else {
+                        pathing.cooldown = Duration::from_secs(0);
+                    }
+
+                    if repaths_left > 0 && pathing.needs_repath(player_tile) {
+                        repaths_left -= 1;
+                        let path = find_path(&game_map, enemy_tile, player_tile).unwrap_or_default();
+                        pathing.set_path(path, player_tile);
+                    }
+
+                    // Aim to have the collision box centred on the next waypoint
+                    while let Some(&waypoint) = pathing.path.first() {
+                        let waypoint_pos = Aabb::new(
+                            Aabb::tile(waypoint).center,
+                            Aabb::of(pos, coll_flag).half_extents,
+                        )
+                        .corner()
+                        .0;
+                        let dist = (waypoint_pos[0] - pos.0[0]).abs() + (waypoint_pos[1] - pos.0[1]).abs();
+
+                        if waypoint == player_tile || dist > step {
+                            if waypoint != player_tile {
+                                target = waypoint_pos;
+                            }
+                            break;
+                        }
+                        pathing.path.remove(0);
+                    }
+                }
+
+                let pos_diff = [target[0] - pos.0[0], target[1] - pos.0[1]];
                 let r = (pos_diff[0] * pos_diff[0] + pos_diff[1] * pos_diff[1]).sqrt();
 
-                let speed = 2.;
+                if r > 0. {
+                    try_move(pos, 0, step * pos_diff[0] / r, &game_map, coll_flag);
+                    try_move(pos, 1, step * pos_diff[1] / r, &game_map, coll_flag);
+                }
+            }
+
+        }
+    }
+}
+
+/// How quickly knockback dies down, per second
+const KNOCKBACK_DAMPING: f32 = 8.;
+/// Knockback slower than this is dropped
+const KNOCKBACK_REST_SPEED: f32 = 5.;
+
+/// Slides everything that has been shoved along its knockback, stopping at walls
+pub struct ApplyKnockback;
+
+impl<'a> System<'a> for ApplyKnockback {
+    type SystemData = (
+        WriteStorage<'a, GamePosition>,
+        WriteStorage<'a, Knockback>,
+        ReadStorage<'a, CollisionDetectionFlag>,
+        ReadExpect<'a, GameMap>,
+        Read<'a, Time>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut game_poses, mut knockbacks, coll_flags, game_map, time) = data;
+        let dt = time.delta_seconds();
+        let decay = (-KNOCKBACK_DAMPING * dt).exp();
+
+        for (pos, knockback, coll_flag) in (&mut game_poses, &mut knockbacks, &coll_flags).join() {
+            for idx in 0..2 {
+                let wanted = knockback.velocity[idx] * dt;
+                let moved = try_move(pos, idx, wanted, &game_map, coll_flag);
+
+                // Running into a wall soaks up the push along that axis
+                knockback.velocity[idx] = if moved.abs() < wanted.abs() {
+                    0.
+                } else {
+                    knockback.velocity[idx] * decay
+                };
+            }
+
+            let speed = knockback.velocity[0].hypot(knockback.velocity[1]);
+            if speed < KNOCKBACK_REST_SPEED {
+                knockback.velocity = [0., 0.];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_scale::DifficultyMode;
+    use crate::pausable_game_data::FIXED_TICKS_PER_SECOND;
+
+    #[test]
+    fn dash_covers_its_distance() {
+        let difficulty = Difficulty {
+            mode: DifficultyMode::Normal,
+            preset: "normal".to_string(),
+            starting_time: 180,
+            hit_penalty: 15,
+            floor_bonus: 10,
+            dash_cost: 1,
+            enemy_count_scale: 1.,
+            bullet_speed_scale: 1.,
+        };
+        let tick = Duration::from_nanos(1_000_000_000 / FIXED_TICKS_PER_SECOND as u64);
+
+        for &direction in &[[1., 0.], [0., -1.], [1., 1.]] {
+            let mut player = Player::default();
+            start_dash(&mut player, direction, &mut TimeLeft::new(100), &difficulty);
 
-                try_move(pos, 0, speed * pos_diff[0] / r, &game_map, coll_flag);
-                try_move(pos, 1, speed * pos_diff[1] / r, &game_map, coll_flag);
+            let mut moved = [0f32, 0.];
+            while player.dash_time > Duration::from_secs(0) {
+                let step = dash_step(&mut player, tick);
+                moved = [moved[0] + step[0], moved[1] + step[1]];
             }
 
+            assert!((moved[0].hypot(moved[1]) - DASH_DISTANCE).abs() < 0.01, "{:?}", moved);
         }
     }
 }
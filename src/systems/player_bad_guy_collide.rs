@@ -1,55 +1,172 @@
+use crate::aabb::Aabb;
+use crate::collision::CollisionEvent;
 use crate::game::{
-    CollisionDetectionFlag, EnemyFlag, GamePosition, Player, PlayerEntity, TimeLeft,
+    CollisionDetectionFlag, EnemyFlag, GamePosition, Knockback, Player, PlayerEntity, TimeLeft,
 };
-use amethyst::ecs::{Join, Read, ReadStorage, System, WriteStorage};
+use crate::game_scale::Difficulty;
+use crate::relics::{Relics, Stat};
+use amethyst::core::timing::Time;
+use amethyst::ecs::{
+    Entity, Read, ReadExpect, ReadStorage, Resources, System, SystemData, WriteStorage,
+};
+use amethyst::shrev::{EventChannel, ReaderId};
 use std::time::Duration;
 
-pub struct PlayerBadGuyCollide;
+/// Speed the player is shoved away from an enemy they touch
+const PLAYER_KNOCKBACK: f32 = 900.;
+/// Speed the enemy is shoved back the other way
+const ENEMY_KNOCKBACK: f32 = 450.;
+/// How hard overlapping enemies push each other apart, in units per second squared
+const ENEMY_SEPARATION: f32 = 1200.;
+
+/// Unit vector from the center of `from` to the center of `to`, straight up when they match
+fn direction(
+    from: (&GamePosition, &CollisionDetectionFlag),
+    to: (&GamePosition, &CollisionDetectionFlag),
+) -> [f32; 2] {
+    let from = Aabb::of(from.0, from.1).center;
+    let to = Aabb::of(to.0, to.1).center;
+    let diff = [to[0] - from[0], to[1] - from[1]];
+    let r = (diff[0] * diff[0] + diff[1] * diff[1]).sqrt();
+
+    if r > 0. {
+        [diff[0] / r, diff[1] / r]
+    } else {
+        [0., 1.]
+    }
+}
+
+fn positions<'s>(
+    game_poses: &'s ReadStorage<GamePosition>,
+    coll_flags: &'s ReadStorage<CollisionDetectionFlag>,
+    ent: Entity,
+) -> Option<(&'s GamePosition, &'s CollisionDetectionFlag)> {
+    match (game_poses.get(ent), coll_flags.get(ent)) {
+        (Some(pos), Some(coll)) => Some((pos, coll)),
+        _ => None,
+    }
+}
+
+#[derive(Default)]
+pub struct PlayerBadGuyCollide {
+    collisions: Option<ReaderId<CollisionEvent>>,
+}
 
 impl<'a> System<'a> for PlayerBadGuyCollide {
     type SystemData = (
-        WriteStorage<'a, GamePosition>,
+        ReadStorage<'a, GamePosition>,
         ReadStorage<'a, EnemyFlag>,
         ReadStorage<'a, CollisionDetectionFlag>,
         Read<'a, PlayerEntity>,
         WriteStorage<'a, TimeLeft>,
         WriteStorage<'a, Player>,
+        ReadExpect<'a, Difficulty>,
+        Read<'a, EventChannel<CollisionEvent>>,
+        WriteStorage<'a, Knockback>,
+        ReadStorage<'a, Relics>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut game_poses, enemy_flag, coll_flags, player_ent, mut health_store, mut player_flag) =
-            data;
+        let (
+            game_poses,
+            enemy_flag,
+            coll_flags,
+            player_ent,
+            mut health_store,
+            mut player_flag,
+            difficulty,
+            collisions,
+            mut knockbacks,
+            relics_store,
+        ) = data;
 
-        let player = player_flag.get_mut(player_ent.0.unwrap()).unwrap();
-        let player_pos = game_poses.get(player_ent.0.unwrap()).unwrap().clone();
-        let player_coll = coll_flags.get(player_ent.0.unwrap()).unwrap().clone();
-        let mut player_time = health_store.get_mut(player_ent.0.unwrap()).unwrap();
+        let player_ent = player_ent.0.unwrap();
+        let player = player_flag.get_mut(player_ent).unwrap();
+        let player_box = positions(&game_poses, &coll_flags, player_ent).unwrap();
+        let player_time = health_store.get_mut(player_ent).unwrap();
 
-        for (_, pos, coll_flag) in (&enemy_flag, &mut game_poses, &coll_flags).join() {
-            if check_collision(player_pos, player_coll, *pos, *coll_flag) {
-                if player.invincible_time == Duration::from_secs(0) {
-                    player_time.subtract(Duration::from_secs(15));
-                    player.invincible_time = Duration::from_secs(1);
-                }
+        for event in collisions.read(self.collisions.as_mut().unwrap()) {
+            let enemy = match enemy_flag.get(event.b) {
+                Some(enemy) if event.a == player_ent => enemy,
+                _ => continue,
+            };
+            let enemy_box = match positions(&game_poses, &coll_flags, event.b) {
+                Some(enemy_box) => enemy_box,
+                None => continue,
+            };
 
-                warn!("COLLISION!!");
+            // Touching lasts many ticks, only the first one hurts and shoves
+            if player.invincible_time > Duration::from_secs(0) {
+                continue;
+            }
 
-                let player_center_x = player_pos.0[0] + player_coll.0[0] / 2.;
-                let player_center_y = player_pos.0[1] + player_coll.0[1] / 2.;
+            let relics = relics_store.get(player_ent).unwrap();
+            player_time.subtract(relics.seconds(Stat::HitPenalty, difficulty.hit_penalty));
+            player.invincible_time = Duration::from_secs(1);
 
-                let guy_center_x = pos.0[0] + coll_flag.0[0] / 2.;
-                let guy_center_y = pos.0[1] + coll_flag.0[1] / 2.;
+            let away = direction(enemy_box, player_box);
+            Knockback::push(
+                &mut knockbacks,
+                player_ent,
+                [away[0] * PLAYER_KNOCKBACK, away[1] * PLAYER_KNOCKBACK],
+            );
+            if enemy.moves {
+                Knockback::push(
+                    &mut knockbacks,
+                    event.b,
+                    [-away[0] * ENEMY_KNOCKBACK, -away[1] * ENEMY_KNOCKBACK],
+                );
+            }
+        }
+    }
 
-                let diff_x = player_center_x - guy_center_x;
-                let diff_y = player_center_y - guy_center_y;
+    fn setup(&mut self, res: &mut Resources) {
+        Self::SystemData::setup(res);
+        self.collisions = Some(res.fetch_mut::<EventChannel<CollisionEvent>>().register_reader());
+    }
+}
 
-                let r = (diff_x * diff_x + diff_y * diff_y).sqrt();
+/// Nudges enemies that overlap each other apart, so they don't stack on the same spot
+#[derive(Default)]
+pub struct SeparateEnemies {
+    collisions: Option<ReaderId<CollisionEvent>>,
+}
 
-                //pos.0[0] += -50. * diff_x / r;
-                //pos.0[1] += -50. * diff_y / r;
+impl<'a> System<'a> for SeparateEnemies {
+    type SystemData = (
+        ReadStorage<'a, GamePosition>,
+        ReadStorage<'a, EnemyFlag>,
+        ReadStorage<'a, CollisionDetectionFlag>,
+        Read<'a, EventChannel<CollisionEvent>>,
+        WriteStorage<'a, Knockback>,
+        Read<'a, Time>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (game_poses, enemy_flag, coll_flags, collisions, mut knockbacks, time) = data;
+        let push = ENEMY_SEPARATION * time.delta_seconds();
+
+        // Each overlapping pair sends an event both ways, so every event only pushes `a`
+        for event in collisions.read(self.collisions.as_mut().unwrap()) {
+            match (enemy_flag.get(event.a), enemy_flag.get(event.b)) {
+                (Some(enemy), Some(_)) if enemy.moves => (),
+                _ => continue,
+            }
+
+            if let (Some(a), Some(b)) = (
+                positions(&game_poses, &coll_flags, event.a),
+                positions(&game_poses, &coll_flags, event.b),
+            ) {
+                let away = direction(b, a);
+                Knockback::push(&mut knockbacks, event.a, [away[0] * push, away[1] * push]);
             }
         }
     }
+
+    fn setup(&mut self, res: &mut Resources) {
+        Self::SystemData::setup(res);
+        self.collisions = Some(res.fetch_mut::<EventChannel<CollisionEvent>>().register_reader());
+    }
 }
 
 pub fn check_collision(
@@ -58,20 +175,5 @@ pub fn check_collision(
     game_pos2: GamePosition,
     coll_flag2: CollisionDetectionFlag,
 ) -> bool {
-    //    if ( Abs(a.c[0] - b.c[0]) > (a.r[0] + b.r[0]) ) return false;
-    //    if ( Abs(a.c[1] - b.c[1]) > (a.r[1] + b.r[1]) ) return false;
-
-    if (game_pos1.0[0] - game_pos2.0[0]).abs() > coll_flag1.0[0] / 2. + coll_flag2.0[0] / 2. {
-        return false;
-    }
-
-    if (game_pos1.0[1] - game_pos2.0[1]).abs() > coll_flag1.0[1] / 2. + coll_flag2.0[1] / 2. {
-        return false;
-    }
-    true
-
-    //    game_pos1.0[0] - coll_flag1.0[0]/2. < game_pos2.0[0] + coll_flag2.0[0]
-    //        && game_pos1.0[0] + coll_flag1.0[0] > game_pos2.0[0]
-    //        && game_pos1.0[1] < game_pos2.0[1] + coll_flag2.0[1]
-    //        && game_pos1.0[1] + coll_flag1.0[1] > game_pos2.0[1]
+    Aabb::of(&game_pos1, &coll_flag1).overlaps(&Aabb::of(&game_pos2, &coll_flag2))
 }
@@ -1,18 +1,25 @@
 use crate::game::{
-    CollisionDetectionFlag, EnemyFlag, GamePosition, LoadedSpriteSheet, Player, PlayerEntity,
-    ShootDirection, Shooter, TimeLeft, WallFlag,
+    AttackPattern, CollisionDetectionFlag, EnemyFlag, GameMap, GamePosition, LoadedSpriteSheet, Player, PlayerEntity,
+    RoomFlag, ShootDirection, Shooter, TimeLeft, GAME_MAP_X, GAME_MAP_Y, TILE_SIZE,
 };
 use amethyst::core::timing::Time;
 use amethyst::core::transform::Transform;
 use amethyst::ecs::{
-    Component, Entities, Join, LazyUpdate, NullStorage, Read, ReadStorage, System, VecStorage,
-    WriteStorage,
+    Component, Entities, Entity, Join, LazyUpdate, NullStorage, Read, ReadExpect, ReadStorage,
+    Resources, System, SystemData, VecStorage, WriteStorage,
 };
+use amethyst::shrev::{EventChannel, ReaderId};
 use amethyst::input::InputHandler;
+use crate::game_scale::Difficulty;
+use crate::aabb::Aabb;
+use crate::collision::{CollisionEvent, CollisionLayer};
+use crate::weapons::{Weapon, WeaponDefs};
+use crate::relics::{Relics, Stat};
+use crate::actives::{ActiveItem, ActiveKind};
+use std::f32::consts::PI;
 use std::time::Duration;
 
 pub struct PlayerShoot;
-use crate::systems::player_bad_guy_collide::check_collision;
 use amethyst::assets::AssetStorage;
 use amethyst::ecs::Builder;
 use amethyst::renderer::{SpriteRender, SpriteSheetHandle};
@@ -29,6 +36,10 @@ impl<'a> System<'a> for PlayerShoot {
         Entities<'a>,
         WriteStorage<'a, Shooter>,
         AudioSystemData<'a>,
+        WriteStorage<'a, Weapon>,
+        ReadExpect<'a, WeaponDefs>,
+        Read<'a, Time>,
+        ReadStorage<'a, CollisionDetectionFlag>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -42,74 +53,127 @@ impl<'a> System<'a> for PlayerShoot {
             entities,
             mut shooter_store,
             audio,
+            mut weapon_store,
+            weapon_defs,
+            time,
+            coll_flags,
         ) = data;
         //        let (game_poses, coll_flags, player_flag, player_ent, input, lazy) = data;
 
-        let player_pos = game_poses.get(player_ent.0.unwrap()).unwrap().clone();
+        let player_center = Aabb::of(
+            game_poses.get(player_ent.0.unwrap()).unwrap(),
+            coll_flags.get(player_ent.0.unwrap()).unwrap(),
+        )
+        .center;
         let player_shooter = shooter_store.get_mut(player_ent.0.unwrap()).unwrap();
         let mut player = player_flag.get_mut(player_ent.0.unwrap()).unwrap();
-
-        if player_shooter.current_cooldown == Duration::from_secs(0) {
-            let shoot_direction = match (
-                input.action_is_down("shoot_left"),
-                input.action_is_down("shoot_right"),
-                input.action_is_down("shoot_up"),
-                input.action_is_down("shoot_down"),
-            ) {
-                (Some(true), Some(false), Some(false), Some(false)) => ShootDirection::Left,
-                (Some(true), Some(false), Some(true), Some(false)) => ShootDirection::UpLeft,
-                (Some(true), Some(false), Some(false), Some(true)) => ShootDirection::DownLeft,
-                (Some(false), Some(true), Some(false), Some(false)) => ShootDirection::Right,
-                (Some(false), Some(true), Some(true), Some(false)) => ShootDirection::UpRight,
-                (Some(false), Some(true), Some(false), Some(true)) => ShootDirection::DownRight,
-                (Some(false), Some(false), Some(false), Some(true)) => ShootDirection::Down,
-                (Some(false), Some(false), Some(true), Some(false)) => ShootDirection::Up,
-                _ => ShootDirection::None,
-            };
+        let weapon = weapon_store.get_mut(player_ent.0.unwrap()).unwrap();
+        let weapon_def = weapon_defs.get(weapon.kind);
+
+        let shoot_direction = match (
+            input.action_is_down("shoot_left"),
+            input.action_is_down("shoot_right"),
+            input.action_is_down("shoot_up"),
+            input.action_is_down("shoot_down"),
+        ) {
+            (Some(true), Some(false), Some(false), Some(false)) => ShootDirection::Left,
+            (Some(true), Some(false), Some(true), Some(false)) => ShootDirection::UpLeft,
+            (Some(true), Some(false), Some(false), Some(true)) => ShootDirection::DownLeft,
+            (Some(false), Some(true), Some(false), Some(false)) => ShootDirection::Right,
+            (Some(false), Some(true), Some(true), Some(false)) => ShootDirection::UpRight,
+            (Some(false), Some(true), Some(false), Some(true)) => ShootDirection::DownRight,
+            (Some(false), Some(false), Some(false), Some(true)) => ShootDirection::Down,
+            (Some(false), Some(false), Some(true), Some(false)) => ShootDirection::Up,
+            _ => ShootDirection::None,
+        };
+
+        // Charge weapons build up while the keys are held and fire on release
+        let (fire_direction, charge) = if weapon_def.charge_ms > 0 {
             if shoot_direction != ShootDirection::None {
-                player_shooter.set_cooldown();
+                weapon.aim = shoot_direction;
+                weapon.charge += time.delta_time();
+                (ShootDirection::None, 0.)
+            } else {
+                let charge_ms = weapon.charge.as_secs() * 1000 + weapon.charge.subsec_millis() as u64;
+                (weapon.aim, (charge_ms as f32 / weapon_def.charge_ms as f32).min(1.))
+            }
+        } else {
+            (shoot_direction, 0.)
+        };
+
+        if player_shooter.current_cooldown == Duration::from_secs(0)
+            && fire_direction != ShootDirection::None
+        {
+            let rate_ms = player_shooter.shoot_rate.as_secs() * 1000
+                + player_shooter.shoot_rate.subsec_millis() as u64;
+            player_shooter.current_cooldown =
+                Duration::from_millis((rate_ms as f32 * weapon_def.fire_rate_multi) as u64);
+            weapon.charge = Duration::from_secs(0);
+            weapon.aim = ShootDirection::None;
+
+            let damage = (player.damage as f32 * weapon_def.damage_multi * (1. + 2. * charge))
+                .round()
+                .max(1.) as u64;
+            let size = weapon_def.size * (1. + charge);
+            let speed = player_shooter.speed * weapon_def.speed_multi;
+            let direction = fire_direction.to_vector();
+            let player_speed_multi = 0.2;
+
+            for i in 0..weapon_def.projectiles {
+                let angle = if weapon_def.projectiles > 1 {
+                    weapon_def.spread_degrees
+                        * (i as f32 / (weapon_def.projectiles - 1) as f32 - 0.5)
+                } else {
+                    0.
+                }
+                .to_radians();
+
+                let (sin, cos) = angle.sin_cos();
+                let speed = [
+                    (direction[0] * cos - direction[1] * sin) * speed
+                        + player_speed_multi * player.speed[0],
+                    (direction[0] * sin + direction[1] * cos) * speed
+                        + player_speed_multi * player.speed[1],
+                ];
+
                 let sprite_render = SpriteRender {
                     sprite_sheet: sprite_sheet.0.clone().unwrap(),
-                    sprite_number: 15, // paddle is the first sprite in the sprite_sheet
+                    sprite_number: weapon_def.sprite,
                 };
 
-                let game_pos = player_pos.clone();
-                let transform = Transform::default();
+                let mut transform = Transform::default();
+                transform.set_scale(size / 10., size / 10., 1.);
 
-                let speed = player_shooter.speed;
-
-                let speed = match shoot_direction {
-                    ShootDirection::Left => [-1. * speed, 0.],
-                    ShootDirection::Right => [1. * speed, 0.],
-                    ShootDirection::Up => [0., 1. * speed],
-                    ShootDirection::Down => [0., -1. * speed],
-                    ShootDirection::UpLeft => [-0.707 * speed, 0.707 * speed],
-                    ShootDirection::UpRight => [0.707 * speed, 0.707 * speed],
-                    ShootDirection::DownLeft => [-0.707 * speed, -0.707 * speed],
-                    ShootDirection::DownRight => [-0.707 * speed, -0.707 * speed],
-                    _ => [0., 0.],
+                let bullet = Bullet {
+                    damage,
+                    pierce_left: weapon_def.pierce,
+                    ..Bullet::new(speed)
                 };
 
-                let player_speed_multi = 0.2;
-
-                let speed = [
-                    speed[0] + player_speed_multi * player.speed[0],
-                    speed[1] + player_speed_multi * player.speed[1],
-                ];
-
-                let bullet = Bullet { speed };
-
-                lazy.create_entity(&entities)
+                let mut builder = lazy.create_entity(&entities)
                     .with(sprite_render)
-                    .with(game_pos)
+                    .with(Aabb::new(player_center, [size / 2., size / 2.]).corner())
                     .with(transform)
                     .with(bullet)
                     .with(PlayerBullet)
-                    .with(CollisionDetectionFlag([10., 10.]))
-                    .build();
+                    .with(CollisionLayer::player_bullet())
+                    .with(RoomFlag)
+                    .with(CollisionDetectionFlag([size, size]));
+
+                if weapon_def.homing > 0. {
+                    builder = builder.with(Homing { turn_rate: weapon_def.homing });
+                }
+                if weapon_def.bounces > 0 {
+                    builder = builder.with(Ricochet { bounces_left: weapon_def.bounces });
+                }
+                if weapon_def.range > 0. {
+                    builder = builder.with(MaxRange { remaining: weapon_def.range });
+                }
 
-                play_player_shoot(&audio);
+                builder.build();
             }
+
+            play_player_shoot(&audio);
         }
     }
 }
@@ -118,8 +182,60 @@ impl<'a> System<'a> for PlayerShoot {
 #[storage(VecStorage)]
 pub struct Bullet {
     speed: [f32; 2],
+    /// Seconds taken off an enemy's time on a hit
+    pub damage: u64,
+    pub pierce_left: u32,
+    /// Enemies a piercing bullet already went through, so it only hits each once
+    hit: Vec<Entity>,
+    /// Time left before the bullet is removed, whatever it hit
+    lifetime: Duration,
+}
+
+impl Bullet {
+    /// The bullet lives just long enough to fly corner to corner across the whole map
+    pub fn new(speed: [f32; 2]) -> Self {
+        let map_size = [
+            (GAME_MAP_X as u32 * TILE_SIZE) as f32,
+            (GAME_MAP_Y as u32 * TILE_SIZE) as f32,
+        ];
+        let secs = map_size[0].hypot(map_size[1]) / speed[0].hypot(speed[1]).max(1.);
+
+        Self {
+            speed,
+            damage: 0,
+            pierce_left: 0,
+            hit: Vec::new(),
+            lifetime: Duration::from_millis((secs * 1000.).ceil() as u64),
+        }
+    }
+}
+
+/// Steers the bullet toward the nearest enemy, or toward the player for enemy bullets.
+/// `turn_rate` is the most it turns in a second, in radians.
+#[derive(Component)]
+#[storage(VecStorage)]
+pub struct Homing {
+    pub turn_rate: f32,
+}
+
+/// Bounces off walls instead of breaking on them
+#[derive(Component)]
+#[storage(VecStorage)]
+pub struct Ricochet {
+    pub bounces_left: u32,
+}
+
+/// Distance the bullet may still travel before it fizzles out
+#[derive(Component)]
+#[storage(VecStorage)]
+pub struct MaxRange {
+    pub remaining: f32,
 }
 
+const ENEMY_HOMING_TURN_RATE: f32 = 1.8;
+const ENEMY_HOMING_RANGE: f32 = 900.;
+const ENEMY_RICOCHET_BOUNCES: u32 = 2;
+
 #[derive(Component, Default)]
 #[storage(NullStorage)]
 pub struct PlayerBullet;
@@ -128,6 +244,14 @@ pub struct PlayerBullet;
 #[storage(NullStorage)]
 pub struct EnemyBullet;
 
+/// Split off a player bullet by a Split Shot relic, these don't split again
+#[derive(Component, Default)]
+#[storage(NullStorage)]
+pub struct Shard;
+
+/// How far the shards of a split bullet fan out either side of the bounce
+const SHARD_SPREAD_DEGREES: f32 = 60.;
+
 pub struct DecrementPlayerCoolDowns;
 
 impl<'a> System<'a> for DecrementPlayerCoolDowns {
@@ -148,113 +272,344 @@ impl<'a> System<'a> for DecrementPlayerCoolDowns {
 pub struct MoveBullets;
 
 impl<'a> System<'a> for MoveBullets {
-    type SystemData = (ReadStorage<'a, Bullet>, WriteStorage<'a, GamePosition>);
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, Bullet>,
+        WriteStorage<'a, GamePosition>,
+        WriteStorage<'a, Ricochet>,
+        WriteStorage<'a, MaxRange>,
+        ReadStorage<'a, CollisionDetectionFlag>,
+        ReadExpect<'a, GameMap>,
+        Read<'a, Time>,
+    );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (bullet_store, mut game_posses) = data;
+        let (
+            entities,
+            mut bullet_store,
+            mut game_posses,
+            mut ricochet_store,
+            mut range_store,
+            coll_flags,
+            game_map,
+            time,
+        ) = data;
+        let dt = time.delta_seconds();
+
+        for (ent, bullet, game_pos, coll, ricochet, range) in (
+            &entities,
+            &mut bullet_store,
+            &mut game_posses,
+            &coll_flags,
+            (&mut ricochet_store).maybe(),
+            (&mut range_store).maybe(),
+        )
+            .join()
+        {
+            let old_pos = *game_pos;
+            let step = [bullet.speed[0] * dt, bullet.speed[1] * dt];
+            game_pos.0[0] += step[0];
+            game_pos.0[1] += step[1];
+
+            // Gone through a gap in the walls and out of the map
+            if !game_pos.in_map() {
+                entities.delete(ent).unwrap();
+                continue;
+            }
+
+            if bullet.lifetime > time.delta_time() {
+                bullet.lifetime -= time.delta_time();
+            } else {
+                entities.delete(ent).unwrap();
+                continue;
+            }
+
+            if let Some(range) = range {
+                range.remaining -= (step[0].powi(2) + step[1].powi(2)).sqrt();
+                if range.remaining <= 0. {
+                    entities.delete(ent).unwrap();
+                    continue;
+                }
+            }
+
+            let ricochet = match ricochet {
+                Some(ricochet) if ricochet.bounces_left > 0 => ricochet,
+                _ => continue,
+            };
+
+            let normal = Aabb::of(&old_pos, coll).wall_normal(step, &game_map);
+            if normal != [0., 0.] {
+                let dot = bullet.speed[0] * normal[0] + bullet.speed[1] * normal[1];
+                bullet.speed = [
+                    bullet.speed[0] - 2. * dot * normal[0],
+                    bullet.speed[1] - 2. * dot * normal[1],
+                ];
+                *game_pos = old_pos;
+                ricochet.bounces_left -= 1;
+            }
+        }
+    }
+}
+
+pub struct SteerHomingBullets;
+
+impl<'a> System<'a> for SteerHomingBullets {
+    type SystemData = (
+        WriteStorage<'a, Bullet>,
+        ReadStorage<'a, Homing>,
+        ReadStorage<'a, GamePosition>,
+        ReadStorage<'a, CollisionDetectionFlag>,
+        ReadStorage<'a, PlayerBullet>,
+        ReadStorage<'a, EnemyFlag>,
+        Read<'a, PlayerEntity>,
+        Read<'a, Time>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            mut bullet_store,
+            homing_store,
+            game_poses,
+            coll_flags,
+            player_bullet_flag,
+            enemy_store,
+            player_ent,
+            time,
+        ) = data;
+        let dt = time.delta_seconds();
+
+        let center = |pos: &GamePosition, coll: &CollisionDetectionFlag| Aabb::of(pos, coll).center;
+
+        let player = player_ent.0.unwrap();
+        let player_center = center(game_poses.get(player).unwrap(), coll_flags.get(player).unwrap());
+
+        for (bullet, homing, bullet_pos, bullet_coll, player_bullet) in (
+            &mut bullet_store,
+            &homing_store,
+            &game_poses,
+            &coll_flags,
+            player_bullet_flag.maybe(),
+        )
+            .join()
+        {
+            let from = center(bullet_pos, bullet_coll);
+            let distance = |to: &[f32; 2]| (to[0] - from[0]).powi(2) + (to[1] - from[1]).powi(2);
+
+            let target = if player_bullet.is_some() {
+                (&enemy_store, &game_poses, &coll_flags)
+                    .join()
+                    .map(|(_, pos, coll)| center(pos, coll))
+                    .min_by(|a, b| distance(a).partial_cmp(&distance(b)).unwrap())
+            } else {
+                Some(player_center)
+            };
+
+            let target = match target {
+                Some(target) => target,
+                None => continue,
+            };
+
+            let current = bullet.speed[1].atan2(bullet.speed[0]);
+            let wanted = (target[1] - from[1]).atan2(target[0] - from[0]);
+
+            let mut turn = wanted - current;
+            if turn > PI {
+                turn -= 2. * PI;
+            } else if turn < -PI {
+                turn += 2. * PI;
+            }
+            let max_turn = homing.turn_rate * dt;
+            let angle = current + turn.max(-max_turn).min(max_turn);
 
-        for (bullet, game_pos) in (&bullet_store, &mut game_posses).join() {
-            game_pos.0[0] += bullet.speed[0];
-            game_pos.0[1] += bullet.speed[1];
+            let speed = (bullet.speed[0].powi(2) + bullet.speed[1].powi(2)).sqrt();
+            bullet.speed = [angle.cos() * speed, angle.sin() * speed];
         }
     }
 }
 
-pub struct CheckBulletCollide;
+#[derive(Default)]
+pub struct CheckBulletCollide {
+    collisions: Option<ReaderId<CollisionEvent>>,
+}
 
 impl<'a> System<'a> for CheckBulletCollide {
     type SystemData = (
         Entities<'a>,
         ReadStorage<'a, GamePosition>,
-        ReadStorage<'a, CollisionDetectionFlag>,
-        ReadStorage<'a, Bullet>,
+        WriteStorage<'a, Bullet>,
         ReadStorage<'a, EnemyFlag>,
         WriteStorage<'a, TimeLeft>,
         WriteStorage<'a, Player>,
         Read<'a, PlayerEntity>,
-        Read<'a, LazyUpdate>,
-        ReadStorage<'a, WallFlag>,
+        ReadExpect<'a, GameMap>,
         ReadStorage<'a, PlayerBullet>,
         ReadStorage<'a, EnemyBullet>,
         AudioSystemData<'a>,
+        ReadExpect<'a, Difficulty>,
+        ReadStorage<'a, Ricochet>,
+        Read<'a, EventChannel<CollisionEvent>>,
+        ReadStorage<'a, Relics>,
+        ReadStorage<'a, Shard>,
+        ReadStorage<'a, CollisionDetectionFlag>,
+        ReadStorage<'a, SpriteRender>,
+        Read<'a, LazyUpdate>,
+        Read<'a, Time>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
         let (
             entities,
             game_poses,
-            coll_flags,
-            bullet_store,
+            mut bullet_store,
             enemy_store,
             mut time_left,
             mut player_flag,
             player_ent,
-            lazy,
-            wall_flag,
+            game_map,
             player_bullet_flag,
             enemy_bullet_flag,
             audio,
+            difficulty,
+            ricochet_store,
+            collisions,
+            relics_store,
+            shard_store,
+            coll_flags,
+            sprite_renders,
+            lazy,
+            time,
         ) = data;
 
-        let player = player_flag.get_mut(player_ent.0.unwrap()).unwrap();
-        let player_pos = game_poses.get(player_ent.0.unwrap()).unwrap().clone();
-        let player_coll = coll_flags.get(player_ent.0.unwrap()).unwrap().clone();
-
-        for (ent, _, bullet_pos, bullet_coll, _) in (
-            &entities,
-            &bullet_store,
-            &game_poses,
-            &coll_flags,
-            &player_bullet_flag,
-        )
-            .join()
-        {
-            for (_, enem_pos, health, enem_coll) in
-                (&enemy_store, &game_poses, &mut time_left, &coll_flags).join()
-            {
-                if check_collision(*bullet_pos, *bullet_coll, *enem_pos, *enem_coll) {
-                    entities.delete(ent).unwrap();
-                    play_enemy_hit(&audio);
+        let player_ent = player_ent.0.unwrap();
+        let relics = relics_store.get(player_ent).unwrap();
+        // Bullets deleted this tick, they can still show up in later events
+        let mut spent = Vec::new();
 
-                    health.subtract(Duration::from_secs(player.damage));
-                }
+        for event in collisions.read(self.collisions.as_mut().unwrap()) {
+            let (a, b) = (event.a, event.b);
+            if spent.contains(&a) {
+                continue;
             }
 
-            for (_, enem_pos, enem_coll) in (&wall_flag, &game_poses, &coll_flags).join() {
-                if check_collision(*bullet_pos, *bullet_coll, *enem_pos, *enem_coll) {
-                    entities.delete(ent).unwrap();
+            if player_bullet_flag.get(a).is_some() && enemy_store.get(b).is_some() {
+                let bullet = match bullet_store.get_mut(a) {
+                    Some(bullet) => bullet,
+                    None => continue,
+                };
+                if bullet.hit.contains(&b) {
+                    continue;
                 }
-            }
-        }
 
-        let player_time = time_left.get_mut(player_ent.0.unwrap()).unwrap();
+                play_enemy_hit(&audio);
+                if let Some(health) = time_left.get_mut(b) {
+                    health.subtract(Duration::from_secs(bullet.damage));
+                }
+                bullet.hit.push(b);
 
-        for (ent, _, bullet_pos, bullet_coll, _) in (
-            &entities,
-            &bullet_store,
-            &game_poses,
-            &coll_flags,
-            &enemy_bullet_flag,
-        )
-            .join()
-        {
-            if check_collision(*bullet_pos, *bullet_coll, player_pos, player_coll) {
-                entities.delete(ent).unwrap();
+                if bullet.pierce_left > 0 {
+                    bullet.pierce_left -= 1;
+                } else {
+                    entities.delete(a).unwrap();
+                    spent.push(a);
+                }
+            } else if enemy_bullet_flag.get(a).is_some() && b == player_ent {
+                entities.delete(a).unwrap();
+                spent.push(a);
 
+                let player = player_flag.get_mut(player_ent).unwrap();
                 if player.invincible_time == Duration::from_secs(0) {
                     play_player_hit(&audio);
-                    player_time.subtract(Duration::from_secs(15));
+                    time_left
+                        .get_mut(player_ent)
+                        .unwrap()
+                        .subtract(relics.seconds(Stat::HitPenalty, difficulty.hit_penalty));
                     player.invincible_time = Duration::from_secs(1);
                 }
             }
+        }
+
+        let shards = relics.modify(Stat::WallSplits, 0.).round().max(0.) as u32;
+
+        // Walls are tiles rather than entities, so they never show up as events
+        for (ent, bullet, bullet_pos, coll) in
+            (&entities, &bullet_store, &game_poses, &coll_flags).join()
+        {
+            // Bouncing bullets turn around in `MoveBullets` instead
+            if spent.contains(&ent) || ricochet_store.get(ent).map_or(false, |r| r.bounces_left > 0) {
+                continue;
+            }
 
-            for (_, enem_pos, enem_coll) in (&wall_flag, &game_poses, &coll_flags).join() {
-                if check_collision(*bullet_pos, *bullet_coll, *enem_pos, *enem_coll) {
-                    entities.delete(ent).unwrap();
+            if !Aabb::of(bullet_pos, coll).blocked(&game_map) {
+                continue;
+            }
+            entities.delete(ent).unwrap();
+
+            if shards == 0 || player_bullet_flag.get(ent).is_none() || shard_store.get(ent).is_some() {
+                continue;
+            }
+
+            // Step back out of the wall to see which face it went through
+            let dt = time.delta_seconds();
+            let old_pos = GamePosition([
+                bullet_pos.0[0] - bullet.speed[0] * dt,
+                bullet_pos.0[1] - bullet.speed[1] * dt,
+            ]);
+            let old_box = Aabb::of(&old_pos, coll);
+            let normal = old_box.wall_normal([bullet.speed[0] * dt, bullet.speed[1] * dt], &game_map);
+            if old_box.blocked(&game_map) || normal == [0., 0.] {
+                continue;
+            }
+            let dot = bullet.speed[0] * normal[0] + bullet.speed[1] * normal[1];
+            let bounced = [
+                bullet.speed[0] - 2. * dot * normal[0],
+                bullet.speed[1] - 2. * dot * normal[1],
+            ];
+
+            let size = coll.0[0] / 2.;
+            let center = old_box.center;
+            let damage = (bullet.damage / 2).max(1);
+
+            for i in 0..shards {
+                let angle = if shards > 1 {
+                    SHARD_SPREAD_DEGREES * (i as f32 / (shards - 1) as f32 - 0.5)
+                } else {
+                    0.
+                }
+                .to_radians();
+                let (sin, cos) = angle.sin_cos();
+                let speed = [
+                    bounced[0] * cos - bounced[1] * sin,
+                    bounced[0] * sin + bounced[1] * cos,
+                ];
+
+                let mut transform = Transform::default();
+                transform.set_scale(size / 10., size / 10., 1.);
+
+                let mut builder = lazy
+                    .create_entity(&entities)
+                    .with(Aabb::new(center, [size / 2., size / 2.]).corner())
+                    .with(transform)
+                    .with(Bullet {
+                        damage,
+                        ..Bullet::new(speed)
+                    })
+                    .with(PlayerBullet)
+                    .with(Shard)
+                    .with(CollisionLayer::player_bullet())
+                    .with(RoomFlag)
+                    .with(CollisionDetectionFlag([size, size]));
+                if let Some(sprite_render) = sprite_renders.get(ent) {
+                    builder = builder.with(sprite_render.clone());
                 }
+                builder.build();
             }
         }
     }
+
+    fn setup(&mut self, res: &mut Resources) {
+        Self::SystemData::setup(res);
+        self.collisions = Some(res.fetch_mut::<EventChannel<CollisionEvent>>().register_reader());
+    }
 }
 
 pub struct EnemyShoot;
@@ -269,45 +624,93 @@ impl<'a> System<'a> for EnemyShoot {
         ReadStorage<'a, EnemyFlag>,
         Entities<'a>,
         AudioSystemData<'a>,
+        ReadStorage<'a, CollisionDetectionFlag>,
+        ReadExpect<'a, GameMap>,
+        ReadStorage<'a, AttackPattern>,
+        ReadStorage<'a, ActiveItem>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (game_poses, player_ent, lazy, sprite_sheet, mut shooter_store, enemy_store, entities, audio) =
-            data;
+        let (
+            game_poses,
+            player_ent,
+            lazy,
+            sprite_sheet,
+            mut shooter_store,
+            enemy_store,
+            entities,
+            audio,
+            coll_flags,
+            game_map,
+            pattern_store,
+            active_store,
+        ) = data;
+
+        let active = active_store.get(player_ent.0.unwrap()).unwrap();
+        if active.is_active(ActiveKind::TimeStop) {
+            return;
+        }
 
         let player_pos = game_poses.get(player_ent.0.unwrap()).unwrap().clone();
+        let player_coll = coll_flags.get(player_ent.0.unwrap()).unwrap().clone();
+        let player_tile = player_pos.center_tile(&player_coll);
+        let player_center = Aabb::of(&player_pos, &player_coll).center;
 
-        for (_, enemy_pos, shooter) in (&enemy_store, &game_poses, &mut shooter_store).join() {
-            if shooter.current_cooldown == Duration::from_secs(0) {
+        for (_, enemy_pos, shooter, enemy_coll, pattern) in (
+            &enemy_store,
+            &game_poses,
+            &mut shooter_store,
+            &coll_flags,
+            pattern_store.maybe(),
+        )
+            .join()
+        {
+            // Out of sight the shot stays ready and goes off as soon as the player shows up
+            if shooter.current_cooldown == Duration::from_secs(0)
+                && game_map.has_line_of_sight(enemy_pos.center_tile(enemy_coll), player_tile)
+            {
                 shooter.set_cooldown();
                 let sprite_render = SpriteRender {
                     sprite_sheet: sprite_sheet.0.clone().unwrap(),
                     sprite_number: 10, // paddle is the first sprite in the sprite_sheet
                 };
 
-                let game_pos = enemy_pos.clone();
+                let enemy_center = Aabb::of(enemy_pos, enemy_coll).center;
+                let game_pos = Aabb::new(enemy_center, [5., 5.]).corner();
                 let transform = Transform::default();
 
                 let speed = shooter.speed;
 
                 let pos_diff = [
-                    -game_pos.0[0] + player_pos.0[0],
-                    -game_pos.0[1] + player_pos.0[1],
+                    player_center[0] - enemy_center[0],
+                    player_center[1] - enemy_center[1],
                 ];
                 let r = (pos_diff[0] * pos_diff[0] + pos_diff[1] * pos_diff[1]).sqrt();
 
                 let speed = [speed * pos_diff[0] / r, speed * pos_diff[1] / r];
 
-                let bullet = Bullet { speed };
+                let bullet = Bullet::new(speed);
 
-                lazy.create_entity(&entities)
+                let builder = lazy.create_entity(&entities)
                     .with(sprite_render)
                     .with(game_pos)
                     .with(transform)
                     .with(bullet)
                     .with(EnemyBullet)
-                    .with(CollisionDetectionFlag([10., 10.]))
-                    .build();
+                    .with(CollisionLayer::enemy_bullet())
+                    .with(RoomFlag)
+                    .with(CollisionDetectionFlag([10., 10.]));
+
+                match pattern.cloned().unwrap_or_default() {
+                    AttackPattern::Aimed => builder.build(),
+                    AttackPattern::Homing => builder
+                        .with(Homing { turn_rate: ENEMY_HOMING_TURN_RATE })
+                        .with(MaxRange { remaining: ENEMY_HOMING_RANGE })
+                        .build(),
+                    AttackPattern::Ricochet => builder
+                        .with(Ricochet { bounces_left: ENEMY_RICOCHET_BOUNCES })
+                        .build(),
+                };
 
                 play_enemy_shoot(&audio);
             }
@@ -1,11 +1,15 @@
-use crate::game::{EnemyFlag, GamePosition, ItemType, LoadedSpriteSheet, NumEnemiesLeft, Player, PlayerEntity, TimeLeft, Animation, DEAD_ENEMY_FRAMES, RoomFlag, GameOver};
-use crate::systems::check_next_room::spawn_item;
+use crate::game::{EnemyFlag, FloorsVisited, GamePosition, LoadedSpriteSheet, NumEnemiesLeft, Player, PlayerEntity, TimeLeft, Animation, DEAD_ENEMY_FRAMES, RoomFlag, GameOver};
+use crate::game_scale::DifficultyCurve;
+use crate::items::{DropSource, ItemRegistry};
+use crate::relics::{Relics, Stat};
+use crate::actives::{ActiveItem, ActiveKind, HARVEST_REFUND};
+use crate::systems::check_next_room::spawn_drop;
 use amethyst::core::{timing::Time, Transform};
 use amethyst::ecs::{
     Entities, Join, LazyUpdate, Read, ReadExpect, ReadStorage, System, WriteExpect, WriteStorage, Builder
 };
 use amethyst::renderer::{SpriteRender, Transparent};
-use rand::{thread_rng, Rng};
+use crate::run_rng::RunRng;
 use std::time::Duration;
 use crate::audio::{AudioSystemData, play_enemy_die};
 
@@ -18,10 +22,20 @@ impl<'a> System<'a> for DecrementTime {
         WriteStorage<'a, Player>,
         Read<'a, PlayerEntity>,
         WriteStorage<'a, Animation>,
+        ReadStorage<'a, EnemyFlag>,
+        ReadStorage<'a, ActiveItem>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (game_time, mut time_left, mut player_flag, player_ent, mut anim_store) = data;
+        let (
+            game_time,
+            mut time_left,
+            mut player_flag,
+            player_ent,
+            mut anim_store,
+            enemies,
+            active_store,
+        ) = data;
 
         let player = player_flag.get_mut(player_ent.0.unwrap()).unwrap();
         if player.invincible_time > Duration::from_secs(0) {
@@ -32,7 +46,14 @@ impl<'a> System<'a> for DecrementTime {
             }
         }
 
-        for (time) in (&mut time_left).join() {
+        let time_stopped = active_store
+            .get(player_ent.0.unwrap())
+            .unwrap()
+            .is_active(ActiveKind::TimeStop);
+        for (time, enemy) in (&mut time_left, (&enemies).maybe()).join() {
+            if time_stopped && enemy.is_some() {
+                continue;
+            }
             time.subtract(game_time.delta_time());
         }
 
@@ -52,7 +73,7 @@ pub struct RemoveOutOfTimeBadGuys;
 
 impl<'a> System<'a> for RemoveOutOfTimeBadGuys {
     type SystemData = (
-        ReadStorage<'a, TimeLeft>,
+        WriteStorage<'a, TimeLeft>,
         ReadStorage<'a, EnemyFlag>,
         Read<'a, PlayerEntity>,
         Entities<'a>,
@@ -62,11 +83,17 @@ impl<'a> System<'a> for RemoveOutOfTimeBadGuys {
         ReadExpect<'a, LoadedSpriteSheet>,
         WriteExpect<'a, GameOver>,
         AudioSystemData<'a>,
+        WriteExpect<'a, RunRng>,
+        ReadExpect<'a, ItemRegistry>,
+        ReadExpect<'a, DifficultyCurve>,
+        ReadExpect<'a, FloorsVisited>,
+        ReadStorage<'a, Relics>,
+        ReadStorage<'a, ActiveItem>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
         let (
-            time_left,
+            mut time_left,
             enemies,
             player_ent,
             entities,
@@ -76,32 +103,46 @@ impl<'a> System<'a> for RemoveOutOfTimeBadGuys {
             sprite_sheet,
             mut game_over,
             audio,
+            mut rng,
+            registry,
+            difficulty,
+            floors,
+            relics_store,
+            active_store,
         ) = data;
 
         let zero = Duration::from_secs(0);
+        let relics = relics_store.get(player_ent.0.unwrap()).unwrap();
+        let mut kill_refund = relics.seconds(Stat::KillRefund, 0);
+        if active_store.get(player_ent.0.unwrap()).unwrap().is_active(ActiveKind::Harvest) {
+            kill_refund += Duration::from_secs(HARVEST_REFUND);
+        }
+        let mut refund = zero;
         for (time, _, ent, game_pos) in (&time_left, &enemies, &entities, &game_poses).join() {
             if time.remainaing == zero {
                 play_enemy_die(&audio);
                 entities.delete(ent).unwrap();
                 num_enemies.0 -= 1;
+                refund += kill_refund;
 
-                if thread_rng().gen_bool(0.25) {
-                    spawn_item(
-                        game_pos.to_tile(),
-                        &lazy,
-                        &entities,
-                        &sprite_sheet,
-                        ItemType::PlusTime(10),
-                        0,
-                    );
-                }
+                spawn_drop(
+                    game_pos.to_tile(),
+                    &lazy,
+                    &entities,
+                    &sprite_sheet,
+                    &registry,
+                    DropSource::EnemyDrop,
+                    floors.0,
+                    difficulty.get_powerup_cost(floors.0),
+                    &mut rng,
+                );
 
                 lazy.create_entity(&entities)
                     .with(SpriteRender {
                         sprite_sheet: sprite_sheet.0.clone().unwrap(),
                         sprite_number: 7,
                     })
-                    .with(Animation::new(200, DEAD_ENEMY_FRAMES))
+                    .with(Animation::new(200, DEAD_ENEMY_FRAMES, &mut rng))
                     .with(RoomFlag)
                     .with(game_pos.clone())
                     .with(Transform::default())
@@ -110,7 +151,10 @@ impl<'a> System<'a> for RemoveOutOfTimeBadGuys {
             }
         }
 
-        let player_health = time_left.get(player_ent.0.unwrap()).unwrap();
+        let player_health = time_left.get_mut(player_ent.0.unwrap()).unwrap();
+        if refund > zero {
+            player_health.add(refund);
+        }
 
         if player_health.remainaing == zero {
             game_over.0 = true;
@@ -1,6 +1,16 @@
-use crate::game::{PlayerEntity, TimeLeft, UIEnts};
+use crate::aabb::Aabb;
+use crate::actives::ActiveItem;
+use crate::items::ItemRegistry;
+use crate::relics::Relics;
+use crate::game::{
+    ActiveHud, CollisionDetectionFlag, ForSale, GamePosition, Item, ItemLabel, ItemType,
+    LoadedFonts, PlayerEntity, RelicsHud, RoomFlag, TimeLeft, Tooltip, UIEnts, TILE_SIZE,
+};
+use amethyst::core::Transform;
 use amethyst::ecs::prelude::*;
-use amethyst::ui::UiText;
+use amethyst::ui::{Anchor, UiText, UiTransform};
+use std::collections::HashSet;
+use std::time::Duration;
 
 pub struct UpdateTimer;
 
@@ -34,3 +44,261 @@ impl<'a> System<'a> for UpdateTimer {
         }
     }
 }
+
+const LABEL_COLOR: [f32; 4] = [1., 1., 1., 1.];
+const LABEL_TOO_EXPENSIVE_COLOR: [f32; 4] = [1., 0., 0., 1.];
+
+/// Puts a name and price over every item and clears away the labels of items that are gone.
+/// Prices follow the player's relics, and turn red when the player can't pay them.
+pub struct LabelItems;
+
+impl<'a> System<'a> for LabelItems {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, Item>,
+        ReadStorage<'a, ItemLabel>,
+        WriteStorage<'a, UiText>,
+        ReadStorage<'a, TimeLeft>,
+        ReadExpect<'a, PlayerEntity>,
+        ReadExpect<'a, LoadedFonts>,
+        Read<'a, LazyUpdate>,
+        ReadExpect<'a, ItemRegistry>,
+        ReadStorage<'a, Relics>,
+        ReadStorage<'a, Transform>,
+        WriteStorage<'a, UiTransform>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            item_store,
+            labels,
+            mut ui_text_store,
+            time_left,
+            player_ent,
+            fonts,
+            lazy,
+            registry,
+            relics_store,
+            transforms,
+            mut ui_transforms,
+        ) = data;
+
+        let player_time = time_left.get(player_ent.0.unwrap()).unwrap().remainaing;
+        let relics = relics_store.get(player_ent.0.unwrap()).unwrap();
+
+        let mut labelled = HashSet::new();
+        for (ent, label, ui_text, ui_transform) in
+            (&entities, &labels, &mut ui_text_store, &mut ui_transforms).join()
+        {
+            match item_store.get(label.0) {
+                Some(item) if entities.is_alive(label.0) => {
+                    labelled.insert(label.0);
+                    if let Some(transform) = transforms.get(label.0) {
+                        let [x, y] = above(transform, LABEL_HEIGHT);
+                        ui_transform.local_x = x;
+                        ui_transform.local_y = y;
+                    }
+                    let price = relics.price(item);
+                    let too_expensive = price > 0 && player_time <= Duration::from_secs(price);
+                    ui_text.text = label_text(item, price, &registry);
+                    ui_text.color = if too_expensive {
+                        LABEL_TOO_EXPENSIVE_COLOR
+                    } else {
+                        LABEL_COLOR
+                    };
+                }
+                _ => entities.delete(ent).unwrap(),
+            }
+        }
+
+        for (ent, item, transform) in (&entities, &item_store, &transforms).join() {
+            if labelled.contains(&ent) {
+                continue;
+            }
+
+            let [x, y] = above(transform, LABEL_HEIGHT);
+            let ui_transform = UiTransform::new(
+                format!("item_label_{}", ent.id()),
+                Anchor::BottomLeft,
+                x,
+                y,
+                1.,
+                160.,
+                20.,
+                0,
+            );
+            let text = label_text(item, relics.price(item), &registry);
+            let ui_text = UiText::new(fonts.0.clone(), text, LABEL_COLOR, 18.);
+
+            lazy.create_entity(&entities)
+                .with(ui_transform)
+                .with(ui_text)
+                .with(ItemLabel(ent))
+                .with(RoomFlag)
+                .build();
+        }
+    }
+}
+
+/// How far over the bottom of an item its label sits
+const LABEL_HEIGHT: f32 = TILE_SIZE as f32 + 10.;
+
+/// The spot on screen `height` over the bottom of something drawn at `transform`, which
+/// `GamePositionTransform` has already moved into view and interpolated
+fn above(transform: &Transform, height: f32) -> [f32; 2] {
+    let translation = transform.translation();
+    [translation.x + TILE_SIZE as f32 / 2., translation.y + height]
+}
+
+fn label_text(item: &Item, price: u64, registry: &ItemRegistry) -> String {
+    let name = &registry.get(&item.kind).name;
+    if price > 0 {
+        format!("{} {}s", name, price)
+    } else {
+        name.clone()
+    }
+}
+
+/// How close the player's center has to be to an item's for its tooltip to show
+const TOOLTIP_RANGE: f32 = 1.5 * TILE_SIZE as f32;
+
+/// How far over the bottom of an item the tooltip sits, just over its label
+const TOOLTIP_HEIGHT: f32 = TILE_SIZE as f32 + 32.;
+
+/// Describes the item nearest the player over its label, or shows nothing when none is close
+pub struct ShowItemTooltip;
+
+impl<'a> System<'a> for ShowItemTooltip {
+    type SystemData = (
+        ReadExpect<'a, Tooltip>,
+        WriteStorage<'a, UiText>,
+        WriteStorage<'a, UiTransform>,
+        ReadStorage<'a, Item>,
+        ReadStorage<'a, ForSale>,
+        ReadStorage<'a, GamePosition>,
+        ReadStorage<'a, CollisionDetectionFlag>,
+        ReadExpect<'a, PlayerEntity>,
+        ReadExpect<'a, ItemRegistry>,
+        ReadStorage<'a, Transform>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            tooltip,
+            mut ui_text_store,
+            mut ui_transforms,
+            item_store,
+            for_sale,
+            game_poses,
+            coll_flags,
+            player_ent,
+            registry,
+            transforms,
+        ) = data;
+
+        let player_ent = player_ent.0.unwrap();
+        let player_center = Aabb::of(
+            game_poses.get(player_ent).unwrap(),
+            coll_flags.get(player_ent).unwrap(),
+        )
+        .center;
+
+        let tile = TILE_SIZE as f32;
+        let nearest = (&item_store, &game_poses, &transforms, (&for_sale).maybe())
+            .join()
+            .map(|(item, pos, transform, for_sale)| {
+                let center = [pos.0[0] + tile / 2., pos.0[1] + tile / 2.];
+                let dist = (center[0] - player_center[0]).hypot(center[1] - player_center[1]);
+                (dist, item, transform, for_sale.is_some())
+            })
+            .filter(|&(dist, ..)| dist <= TOOLTIP_RANGE)
+            .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+        let ui_text = ui_text_store.get_mut(tooltip.0).unwrap();
+        let ui_transform = ui_transforms.get_mut(tooltip.0).unwrap();
+
+        match nearest {
+            Some((_, item, transform, for_sale)) => {
+                let description = &registry.get(&item.kind).description;
+                ui_text.text = if for_sale {
+                    format!("{} - E to buy", description)
+                } else {
+                    description.clone()
+                };
+                let [x, y] = above(transform, TOOLTIP_HEIGHT);
+                ui_transform.local_x = x;
+                ui_transform.local_y = y;
+            }
+            None => ui_text.text.clear(),
+        }
+    }
+}
+
+/// Lists the names of the relics the player holds, in the order they were picked up
+pub struct ShowRelics;
+
+impl<'a> System<'a> for ShowRelics {
+    type SystemData = (
+        ReadExpect<'a, RelicsHud>,
+        WriteStorage<'a, UiText>,
+        ReadStorage<'a, Relics>,
+        ReadExpect<'a, PlayerEntity>,
+        ReadExpect<'a, ItemRegistry>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (relics_hud, mut ui_text_store, relics_store, player_ent, registry) = data;
+
+        let relics = relics_store.get(player_ent.0.unwrap()).unwrap();
+        let ui_text = ui_text_store.get_mut(relics_hud.0).unwrap();
+
+        if relics.held.is_empty() {
+            ui_text.text.clear();
+            return;
+        }
+
+        let names: Vec<String> = relics
+            .held
+            .iter()
+            .map(|&kind| registry.get(&ItemType::Relic(kind)).name.clone())
+            .collect();
+        ui_text.text = format!("Relics: {}", names.join(", "));
+    }
+}
+
+/// Shows the held active item and how many more rooms it needs before it can be used
+pub struct ShowActiveItem;
+
+impl<'a> System<'a> for ShowActiveItem {
+    type SystemData = (
+        ReadExpect<'a, ActiveHud>,
+        WriteStorage<'a, UiText>,
+        ReadStorage<'a, ActiveItem>,
+        ReadExpect<'a, PlayerEntity>,
+        ReadExpect<'a, ItemRegistry>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (active_hud, mut ui_text_store, active_store, player_ent, registry) = data;
+
+        let active = active_store.get(player_ent.0.unwrap()).unwrap();
+        let ui_text = ui_text_store.get_mut(active_hud.0).unwrap();
+
+        let kind = match active.held {
+            Some(kind) => kind,
+            None => {
+                ui_text.text.clear();
+                return;
+            }
+        };
+
+        let name = &registry.get(&ItemType::Active(kind)).name;
+        ui_text.text = if active.ready() {
+            format!("{} - Q to use", name)
+        } else {
+            let rooms = kind.rooms_to_charge() - active.charge;
+            format!("{} - clear {} more room{}", name, rooms, if rooms == 1 { "" } else { "s" })
+        };
+    }
+}
@@ -0,0 +1,125 @@
+use amethyst::input::{is_close_requested, is_key_down};
+use amethyst::prelude::*;
+
+use crate::pausable_game_data::PausableGameData;
+use amethyst::core::Transform;
+use amethyst::ecs::prelude::Entity;
+use amethyst::renderer::{SpriteRender, Transparent, VirtualKeyCode};
+use amethyst::ui::{Anchor, UiText, UiTransform};
+
+use crate::game::{
+    initialise_camera, load_fonts, load_sprite_sheet, Game, LoadedFonts, LoadedSpriteSheet,
+    ARENA_HEIGHT, ARENA_WIDTH,
+};
+use crate::game_scale::DifficultyMode;
+
+/// Lets the player pick a difficulty mode before the run starts
+pub struct TitleScreen {
+    seed: Option<u64>,
+    mode: DifficultyMode,
+    mode_text: Option<Entity>,
+}
+
+impl TitleScreen {
+    pub fn new(seed: Option<u64>) -> Self {
+        Self {
+            seed,
+            mode: DifficultyMode::default(),
+            mode_text: None,
+        }
+    }
+
+    fn mode_label(&self) -> String {
+        format!("< {:?} >   1 Easy  2 Normal  3 Hard   Space to start", self.mode)
+    }
+}
+
+impl<'a, 'b> State<PausableGameData<'a, 'b>, StateEvent> for TitleScreen {
+    fn on_start(&mut self, data: StateData<PausableGameData>) {
+        let world = data.world;
+
+        world.delete_all();
+
+        let sprite_sheet = load_sprite_sheet(world);
+        world.add_resource(LoadedSpriteSheet(Some(sprite_sheet.clone())));
+
+        let (font1, font2) = load_fonts(world);
+        world.add_resource(LoadedFonts(font1.clone(), font2.clone()));
+
+        let mut transform = Transform::default();
+        transform.set_xyz(ARENA_WIDTH/2., ARENA_HEIGHT/2., 0.9);
+
+        world.create_entity()
+            .with(transform)
+            .with(SpriteRender {
+                sprite_sheet,
+                sprite_number: 51,
+            })
+            .with(Transparent)
+            .build();
+
+        let transform = UiTransform::new(
+            "difficulty".to_string(),
+            Anchor::TopLeft,
+            683.,
+            -680.,
+            0.95,
+            1000.,
+            50.,
+            0,
+        );
+        let uitext = UiText::new(font1, self.mode_label(), [1., 1., 1., 1.], 40.);
+
+        self.mode_text = Some(world.create_entity()
+            .with(transform)
+            .with(uitext)
+            .build());
+
+        initialise_camera(world);
+    }
+
+    fn handle_event(
+        &mut self,
+        data: StateData<PausableGameData>,
+        event: StateEvent,
+    ) -> Trans<PausableGameData<'a, 'b>, StateEvent> {
+        if let StateEvent::Window(event) = &event {
+            let picked = if is_key_down(&event, VirtualKeyCode::Key1) {
+                Some(DifficultyMode::Easy)
+            } else if is_key_down(&event, VirtualKeyCode::Key2) {
+                Some(DifficultyMode::Normal)
+            } else if is_key_down(&event, VirtualKeyCode::Key3) {
+                Some(DifficultyMode::Hard)
+            } else {
+                None
+            };
+
+            if let Some(mode) = picked {
+                self.mode = mode;
+                if let Some(ent) = self.mode_text {
+                    if let Some(text) = data.world.write_storage::<UiText>().get_mut(ent) {
+                        text.text = self.mode_label();
+                    }
+                }
+            }
+
+            if is_close_requested(&event) {
+                Trans::Quit
+            } else if is_key_down(&event, VirtualKeyCode::Space) {
+                Trans::Switch(Box::new(Game::new(self.seed, self.mode)))
+            } else {
+                Trans::None
+            }
+        } else {
+            Trans::None
+        }
+    }
+
+    fn update(
+        &mut self,
+        data: StateData<PausableGameData>,
+    ) -> Trans<PausableGameData<'a, 'b>, StateEvent> {
+        data.data.update(data.world, false);
+        Trans::None
+    }
+}
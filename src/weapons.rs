@@ -0,0 +1,122 @@
+use crate::game::{Item, ItemType, ShootDirection};
+use amethyst::ecs::prelude::{Component, VecStorage};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum WeaponKind {
+    Basic,
+    Spread,
+    Beam,
+    Bouncer,
+    Charge,
+    Seeker,
+}
+
+/// How a weapon fires, read from `resources/weapons.ron`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WeaponDef {
+    pub name: String,
+    /// Bullets per shot, fanned out evenly over `spread_degrees`
+    pub projectiles: u32,
+    pub spread_degrees: f32,
+    pub speed_multi: f32,
+    pub damage_multi: f32,
+    /// Scales the time between shots of the player's `Shooter`
+    pub fire_rate_multi: f32,
+    /// Enemies a bullet passes through before it is used up
+    pub pierce: u32,
+    /// Times a bullet bounces off walls before it is destroyed
+    pub bounces: u32,
+    /// When non zero, holding the shoot keys charges the shot and releasing fires it.
+    /// A full charge triples the damage and doubles the size.
+    pub charge_ms: u64,
+    /// Radians per second the bullets turn toward the nearest enemy, 0 to fly straight
+    #[serde(default)]
+    pub homing: f32,
+    /// Distance the bullets fly before fizzling out, 0 for no limit
+    #[serde(default)]
+    pub range: f32,
+    pub size: f32,
+    pub sprite: usize,
+}
+
+const WEAPON_KINDS: [WeaponKind; 6] = [
+    WeaponKind::Basic,
+    WeaponKind::Spread,
+    WeaponKind::Beam,
+    WeaponKind::Bouncer,
+    WeaponKind::Charge,
+    WeaponKind::Seeker,
+];
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct WeaponDefs(pub HashMap<WeaponKind, WeaponDef>);
+
+impl WeaponDefs {
+    /// Panics on any weapon weapons.ron leaves out, so `get` can't fail later
+    pub fn validate(&self) {
+        for kind in WEAPON_KINDS.iter() {
+            if !self.0.contains_key(kind) {
+                panic!("weapons.ron doesn't define {:?}", kind);
+            }
+        }
+    }
+
+    pub fn get(&self, kind: WeaponKind) -> &WeaponDef {
+        &self.0[&kind]
+    }
+}
+
+/// The weapon the player is holding
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct Weapon {
+    pub kind: WeaponKind,
+    pub charge: Duration,
+    /// Direction the charge shot goes off in when the keys are released
+    pub aim: ShootDirection,
+}
+
+impl Weapon {
+    pub fn new(kind: WeaponKind) -> Self {
+        Self {
+            kind,
+            charge: Duration::from_secs(0),
+            aim: ShootDirection::None,
+        }
+    }
+
+    pub fn swap(&mut self, kind: WeaponKind) {
+        *self = Weapon::new(kind);
+    }
+
+    pub fn apply_item(&mut self, item: &Item) {
+        match item.kind {
+            ItemType::Weapon(kind) => {
+                self.swap(kind);
+            }
+            _ => (),
+        };
+    }
+}
+
+impl Default for Weapon {
+    fn default() -> Self {
+        Weapon::new(WeaponKind::Basic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use amethyst::config::Config;
+    use std::path::Path;
+
+    #[test]
+    fn weapons_file_is_complete() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/weapons.ron");
+        WeaponDefs::load_no_fallback(&path).unwrap().validate();
+    }
+}